@@ -0,0 +1,744 @@
+use crate::item::{Algorithm, Item, Kind, MAX_DIGITS, MIN_DIGITS};
+use crate::util::{import_label, normalize_secret, parse_tags};
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+const FILE_VERSION: u32 = 1;
+const DATABASE_VERSION: u32 = 3;
+const PASSWORD_SLOT: u32 = 1;
+const KEY_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 12;
+const TAG_LENGTH: usize = 16;
+// The scrypt cost Aegis itself uses for password slots, N = 2^15.
+const DEFAULT_LOG_N: u8 = 15;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+// Limits on the scrypt parameters read from a vault, which would otherwise decide how much memory
+// and time is spent before the password can be checked. Aegis itself uses n = 2^15, r = 8, p = 1.
+const MAX_SCRYPT_N: u64 = 1 << 20;
+const MAX_SCRYPT_MEMORY: u64 = 1 << 30;
+const MAX_SCRYPT_P: u32 = 16;
+
+/// The items read from an Aegis vault, along with the entries that could not be imported and
+/// the fields of imported entries that were dropped.
+#[derive(Debug)]
+pub struct Import {
+    pub items: Vec<Item>,
+    pub skipped: Vec<(String, String)>,
+    pub ignored: Vec<(String, String)>,
+}
+
+/// An Aegis vault built from the items, along with the items that could not be exported and
+/// the fields of exported items that were dropped.
+#[derive(Debug)]
+pub struct Export {
+    pub vault: String,
+    pub exported: usize,
+    pub skipped: Vec<(String, String)>,
+    pub ignored: Vec<(String, String)>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct VaultFile {
+    version: u32,
+    header: Header,
+    // An object for plain vaults, a base-64 string for encrypted ones.
+    db: serde_json::Value,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    slots: Option<Vec<Slot>>,
+    params: Option<CipherParams>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Slot {
+    #[serde(rename = "type")]
+    kind: u32,
+    uuid: String,
+    key: String,
+    key_params: CipherParams,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    n: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    r: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    p: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    salt: Option<String>,
+    #[serde(default)]
+    repaired: bool,
+    #[serde(default)]
+    is_backup: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CipherParams {
+    nonce: String,
+    tag: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Database {
+    version: u32,
+    entries: Vec<Entry>,
+    // Only present in version 3 databases.
+    #[serde(default)]
+    groups: Vec<Group>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Group {
+    uuid: String,
+    name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    #[serde(rename = "type")]
+    kind: String,
+    uuid: String,
+    name: String,
+    #[serde(default)]
+    issuer: String,
+    #[serde(default)]
+    note: String,
+    #[serde(default)]
+    favorite: bool,
+    #[serde(default)]
+    icon: Option<String>,
+    info: Info,
+    // Version 2 databases name a single group, version 3 refers to groups by uuid.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    group: Option<String>,
+    #[serde(default)]
+    groups: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Info {
+    secret: String,
+    algo: String,
+    digits: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    period: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    counter: Option<u64>,
+}
+
+/// Returns true if the vault is encrypted and a password is needed to import it.
+pub fn is_encrypted(contents: &str) -> Result<bool, String> {
+    return Ok(parse_file(contents)?.db.is_string());
+}
+
+pub fn import_vault(contents: &str, password: Option<&str>) -> Result<Import, String> {
+    let file = parse_file(contents)?;
+    let database: Database;
+
+    match (&file.db, &file.header.slots, &file.header.params) {
+        (serde_json::Value::String(data), Some(slots), Some(params)) => {
+            let password = match password {
+                Some(p) => p,
+                None => return Err(String::from("The Aegis vault is encrypted.")),
+            };
+
+            let master_key = unlock_master_key(slots, password)?;
+            let plaintext = decrypt(&master_key, params, &decode_base64(data)?)?;
+
+            match serde_json::from_slice(&plaintext) {
+                Ok(d) => database = d,
+                Err(e) => return Err(format!("The Aegis database is malformed: {}", e)),
+            }
+        }
+        (serde_json::Value::Object(_), _, _) => match serde_json::from_value(file.db) {
+            Ok(d) => database = d,
+            Err(e) => return Err(format!("The Aegis database is malformed: {}", e)),
+        },
+        _ => return Err(String::from("The Aegis vault is malformed.")),
+    }
+
+    let mut import = Import {
+        items: Vec::new(),
+        skipped: Vec::new(),
+        ignored: Vec::new(),
+    };
+
+    for entry in database.entries {
+        let label = import_label(&entry.issuer, &entry.name);
+
+        match entry_to_item(&entry, &label, &database.groups) {
+            Ok(item) => {
+                for field in ignored_fields(&entry) {
+                    import.ignored.push((label.clone(), String::from(field)));
+                }
+
+                import.items.push(item);
+            }
+            Err(reason) => import.skipped.push((label, reason)),
+        }
+    }
+
+    return Ok(import);
+}
+
+/// Builds an Aegis vault containing the items, encrypted with a single password slot when a
+/// password is given.
+pub fn export_vault(items: &Vec<Item>, password: Option<&str>) -> Result<Export, String> {
+    return export_vault_with_cost(items, password, DEFAULT_LOG_N);
+}
+
+pub fn export_vault_with_cost(
+    items: &Vec<Item>,
+    password: Option<&str>,
+    log_n: u8,
+) -> Result<Export, String> {
+    let mut database = Database {
+        version: DATABASE_VERSION,
+        entries: Vec::new(),
+        groups: Vec::new(),
+    };
+    let mut skipped = Vec::new();
+    let mut ignored = Vec::new();
+
+    for item in items.iter() {
+        match item_to_entry(item, &mut database.groups)? {
+            Ok(entry) => {
+                if item.time_offset != 0 {
+                    ignored.push((item.label.clone(), String::from("time offset")));
+                }
+
+                database.entries.push(entry);
+            }
+            Err(reason) => skipped.push((item.label.clone(), reason)),
+        }
+    }
+
+    let file;
+
+    match password {
+        Some(password) => {
+            let master_key = random_bytes(KEY_LENGTH)?;
+            let salt = random_bytes(KEY_LENGTH)?;
+            let slot_key = derive_key(password, &salt, log_n, SCRYPT_R, SCRYPT_P)?;
+            let (key, key_params) = encrypt(&slot_key, &master_key)?;
+
+            let plaintext;
+            match serde_json::to_vec(&database) {
+                Ok(p) => plaintext = p,
+                Err(e) => return Err(format!("Could not serialize the Aegis database: {}", e)),
+            }
+
+            let (data, params) = encrypt(&master_key, &plaintext)?;
+
+            file = VaultFile {
+                version: FILE_VERSION,
+                header: Header {
+                    slots: Some(vec![Slot {
+                        kind: PASSWORD_SLOT,
+                        uuid: random_uuid()?,
+                        key: hex::encode(key),
+                        key_params,
+                        n: Some(1 << log_n),
+                        r: Some(SCRYPT_R),
+                        p: Some(SCRYPT_P),
+                        salt: Some(hex::encode(salt)),
+                        repaired: true,
+                        is_backup: false,
+                    }]),
+                    params: Some(params),
+                },
+                db: serde_json::Value::String(BASE64.encode(data)),
+            };
+        }
+        None => match serde_json::to_value(&database) {
+            Ok(db) => {
+                file = VaultFile {
+                    version: FILE_VERSION,
+                    header: Header {
+                        slots: None,
+                        params: None,
+                    },
+                    db,
+                }
+            }
+            Err(e) => return Err(format!("Could not serialize the Aegis database: {}", e)),
+        },
+    }
+
+    match serde_json::to_string_pretty(&file) {
+        Ok(vault) => {
+            return Ok(Export {
+                vault,
+                exported: database.entries.len(),
+                skipped,
+                ignored,
+            })
+        }
+        Err(e) => return Err(format!("Could not serialize the Aegis vault: {}", e)),
+    }
+}
+
+fn parse_file(contents: &str) -> Result<VaultFile, String> {
+    let file: VaultFile;
+    match serde_json::from_str(contents) {
+        Ok(f) => file = f,
+        Err(e) => return Err(format!("The file is not an Aegis vault: {}", e)),
+    }
+
+    if file.version != FILE_VERSION {
+        return Err(format!(
+            "Aegis vault version {} is not supported.",
+            file.version
+        ));
+    }
+
+    return Ok(file);
+}
+
+fn entry_to_item(entry: &Entry, label: &String, groups: &Vec<Group>) -> Result<Item, String> {
+    let kind = match entry.kind.as_str() {
+        "totp" => Kind::Totp,
+        "hotp" => Kind::Hotp,
+        "steam" => Kind::Steam,
+        other => return Err(format!("{} entries are not supported", other)),
+    };
+
+    let algorithm;
+    match entry.info.algo.parse::<Algorithm>() {
+        Ok(a) => algorithm = a,
+        Err(_) => {
+            return Err(format!(
+                "the {} algorithm is not supported",
+                entry.info.algo
+            ))
+        }
+    }
+
+    let digits = match entry.info.digits {
+        d if (MIN_DIGITS..=MAX_DIGITS).contains(&d) => d,
+        d => return Err(format!("{} digit codes are not supported", d)),
+    };
+
+    let secret;
+    match normalize_secret(&entry.info.secret) {
+        Ok(s) => secret = s,
+        Err(_) => return Err(String::from("the secret is not a base-32 string")),
+    }
+
+    return Ok(Item {
+        label: label.clone(),
+        secret,
+        digits,
+        split_time: entry.info.period.unwrap_or(30),
+        kind,
+        counter: entry.info.counter.unwrap_or(0),
+        algorithm,
+        issuer: if entry.issuer.trim().is_empty() {
+            None
+        } else {
+            Some(String::from(entry.issuer.trim()))
+        },
+        account: if entry.name.trim().is_empty() {
+            None
+        } else {
+            Some(String::from(entry.name.trim()))
+        },
+        tags: entry_tags(entry, groups),
+        time_offset: 0,
+        t0: 0,
+    });
+}
+
+/// The groups of an entry become tags. Groups that aren't listed in the database are left out.
+fn entry_tags(entry: &Entry, groups: &Vec<Group>) -> Vec<String> {
+    let mut names: Vec<String> = entry.group.iter().cloned().collect();
+
+    for uuid in entry.groups.iter() {
+        if let Some(group) = groups.iter().find(|g| &g.uuid == uuid) {
+            names.push(group.name.clone());
+        }
+    }
+
+    return parse_tags(&names.join(","));
+}
+
+fn ignored_fields(entry: &Entry) -> Vec<&'static str> {
+    let mut fields = Vec::new();
+
+    if !entry.note.is_empty() {
+        fields.push("note");
+    }
+
+    if entry.icon.is_some() {
+        fields.push("icon");
+    }
+
+    if entry.favorite {
+        fields.push("favorite");
+    }
+
+    return fields;
+}
+
+/// Converts an item to an entry, adding a group for each of its tags that isn't in groups yet.
+/// The reason is returned in the inner error when the item can't be represented in Aegis.
+fn item_to_entry(item: &Item, groups: &mut Vec<Group>) -> Result<Result<Entry, String>, String> {
+    if item.kind.is_time_based() && item.t0 != 0 {
+        return Ok(Err(format!(
+            "it counts its periods from a T0 of {}, which Aegis can't represent",
+            item.t0
+        )));
+    }
+
+    // Aegis keeps the issuer apart from the account name.
+    let name = match (&item.account, &item.issuer) {
+        (Some(account), _) => account,
+        (None, Some(issuer)) => item
+            .label
+            .strip_prefix(&format!("{}:", issuer))
+            .unwrap_or(&item.label),
+        (None, None) => &item.label,
+    };
+
+    let mut group_uuids = Vec::new();
+
+    for tag in item.tags.iter() {
+        match groups
+            .iter()
+            .find(|g| g.name.to_lowercase() == tag.to_lowercase())
+        {
+            Some(group) => group_uuids.push(group.uuid.clone()),
+            None => {
+                let uuid = random_uuid()?;
+                groups.push(Group {
+                    uuid: uuid.clone(),
+                    name: tag.clone(),
+                });
+                group_uuids.push(uuid);
+            }
+        }
+    }
+
+    return Ok(Ok(Entry {
+        kind: item.kind.to_string(),
+        uuid: random_uuid()?,
+        name: String::from(name),
+        issuer: item.issuer.clone().unwrap_or_default(),
+        note: String::new(),
+        favorite: false,
+        icon: None,
+        info: Info {
+            secret: item.secret.to_uppercase(),
+            algo: item.algorithm.to_string(),
+            digits: item.digits,
+            period: match item.kind {
+                Kind::Totp | Kind::Steam => Some(item.split_time),
+                Kind::Hotp => None,
+            },
+            counter: match item.kind {
+                Kind::Totp | Kind::Steam => None,
+                Kind::Hotp => Some(item.counter),
+            },
+        },
+        group: None,
+        groups: group_uuids,
+    }));
+}
+
+/// Decrypts the master key with the first password slot that the password opens.
+fn unlock_master_key(slots: &Vec<Slot>, password: &str) -> Result<Vec<u8>, String> {
+    let mut has_password_slot = false;
+
+    for slot in slots.iter().filter(|s| s.kind == PASSWORD_SLOT) {
+        has_password_slot = true;
+
+        let (n, r, p, salt) = match (slot.n, slot.r, slot.p, &slot.salt) {
+            (Some(n), Some(r), Some(p), Some(salt)) => (n, r, p, salt),
+            _ => return Err(String::from("The Aegis password slot is malformed.")),
+        };
+
+        if !n.is_power_of_two() {
+            return Err(String::from("The Aegis password slot is malformed."));
+        }
+
+        if n > MAX_SCRYPT_N || 128 * n * r as u64 > MAX_SCRYPT_MEMORY || p > MAX_SCRYPT_P {
+            return Err(String::from(
+                "The Aegis password slot asks for a more expensive key derivation than otpc allows.",
+            ));
+        }
+
+        let key = derive_key(password, &decode_hex(salt)?, n.trailing_zeros() as u8, r, p)?;
+
+        match decrypt(&key, &slot.key_params, &decode_hex(&slot.key)?) {
+            Ok(master_key) => return Ok(master_key),
+            Err(_) => continue,
+        }
+    }
+
+    if !has_password_slot {
+        return Err(String::from(
+            "The Aegis vault has no password slot, only password encrypted vaults can be imported.",
+        ));
+    }
+
+    return Err(String::from("The password is incorrect."));
+}
+
+fn derive_key(password: &str, salt: &[u8], log_n: u8, r: u32, p: u32) -> Result<Vec<u8>, String> {
+    let params;
+    match scrypt::Params::new(log_n, r, p, KEY_LENGTH) {
+        Ok(p) => params = p,
+        Err(_) => return Err(String::from("The Aegis scrypt parameters are invalid.")),
+    }
+
+    let mut key = vec![0u8; KEY_LENGTH];
+
+    match scrypt::scrypt(password.as_bytes(), salt, &params, &mut key) {
+        Ok(()) => return Ok(key),
+        Err(_) => return Err(String::from("Could not derive the Aegis key.")),
+    }
+}
+
+fn decrypt(key: &[u8], params: &CipherParams, ciphertext: &[u8]) -> Result<Vec<u8>, String> {
+    let nonce = decode_hex(&params.nonce)?;
+
+    if nonce.len() != NONCE_LENGTH || key.len() != KEY_LENGTH {
+        return Err(String::from("The Aegis encryption parameters are invalid."));
+    }
+
+    // Aegis stores the authentication tag apart from the ciphertext.
+    let mut payload = ciphertext.to_vec();
+    payload.extend(decode_hex(&params.tag)?);
+
+    match Aes256Gcm::new_from_slice(key)
+        .unwrap()
+        .decrypt(Nonce::from_slice(&nonce), payload.as_ref())
+    {
+        Ok(plaintext) => return Ok(plaintext),
+        Err(_) => return Err(String::from("The Aegis vault could not be decrypted.")),
+    }
+}
+
+fn encrypt(key: &[u8], plaintext: &[u8]) -> Result<(Vec<u8>, CipherParams), String> {
+    let nonce = random_bytes(NONCE_LENGTH)?;
+    let mut ciphertext;
+
+    match Aes256Gcm::new_from_slice(key)
+        .unwrap()
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+    {
+        Ok(c) => ciphertext = c,
+        Err(_) => return Err(String::from("Could not encrypt the Aegis vault.")),
+    }
+
+    let tag = ciphertext.split_off(ciphertext.len() - TAG_LENGTH);
+
+    return Ok((
+        ciphertext,
+        CipherParams {
+            nonce: hex::encode(nonce),
+            tag: hex::encode(tag),
+        },
+    ));
+}
+
+fn random_bytes(length: usize) -> Result<Vec<u8>, String> {
+    let mut bytes = vec![0u8; length];
+
+    match getrandom::getrandom(&mut bytes) {
+        Ok(()) => return Ok(bytes),
+        Err(_) => return Err(String::from("Could not generate random data.")),
+    }
+}
+
+/// Generates a random version 4 UUID.
+fn random_uuid() -> Result<String, String> {
+    let mut bytes = random_bytes(16)?;
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex = hex::encode(bytes);
+
+    return Ok(format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    ));
+}
+
+fn decode_hex(value: &str) -> Result<Vec<u8>, String> {
+    match hex::decode(value) {
+        Ok(bytes) => return Ok(bytes),
+        Err(_) => return Err(String::from("The Aegis vault contains invalid hex data.")),
+    }
+}
+
+fn decode_base64(value: &str) -> Result<Vec<u8>, String> {
+    match BASE64.decode(value) {
+        Ok(bytes) => return Ok(bytes),
+        Err(_) => {
+            return Err(String::from(
+                "The Aegis vault contains invalid base-64 data.",
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::item::{Algorithm, Item, Kind};
+
+    fn items() -> Vec<Item> {
+        return vec![
+            Item {
+                label: String::from("Example:alice"),
+                secret: String::from("jbswy3dpehpk3pxp"),
+                digits: 8,
+                split_time: 60,
+                algorithm: Algorithm::Sha256,
+                issuer: Some(String::from("Example")),
+                account: Some(String::from("alice")),
+                tags: vec![String::from("Work"), String::from("Code")],
+                ..Default::default()
+            },
+            Item {
+                label: String::from("bob"),
+                secret: String::from("gezdgnbvgy3tqojq"),
+                kind: Kind::Hotp,
+                counter: 7,
+                account: Some(String::from("bob")),
+                ..Default::default()
+            },
+        ];
+    }
+
+    #[test]
+    pub fn test_import_vault_plain() {
+        use super::*;
+        let vault = r#"{
+            "version": 1,
+            "header": {"slots": null, "params": null},
+            "db": {"version": 2, "groups": [{"uuid": "g1", "name": "Personal"}], "entries": [
+                {"type": "totp", "uuid": "1", "name": "alice@example.com", "issuer": "ACME Co",
+                 "note": "work", "icon": null, "group": "Work", "groups": ["g1", "g2"],
+                 "info": {"secret": "JBSWY3DPEHPK3PXP", "algo": "SHA512", "digits": 7, "period": 45}},
+                {"type": "steam", "uuid": "2", "name": "gabe", "issuer": "Steam",
+                 "info": {"secret": "JBSWY3DPEHPK3PXP", "algo": "SHA1", "digits": 5, "period": 30}},
+                {"type": "totp", "uuid": "3", "name": "carol", "issuer": "",
+                 "info": {"secret": "JBSWY3DPEHPK3PXP", "algo": "MD5", "digits": 6, "period": 30}}
+            ]}
+        }"#;
+
+        assert!(!is_encrypted(vault).unwrap());
+        let import = import_vault(vault, None).unwrap();
+
+        assert_eq!(import.items.len(), 2);
+        assert_eq!(import.items[0].label, "ACME Co:alice@example.com");
+        assert_eq!(import.items[0].issuer, Some(String::from("ACME Co")));
+        assert_eq!(import.items[0].secret, "jbswy3dpehpk3pxp");
+        assert_eq!(import.items[0].algorithm, Algorithm::Sha512);
+        assert_eq!(import.items[0].digits, 7);
+        assert_eq!(import.items[0].split_time, 45);
+        assert_eq!(import.items[0].tags, vec!["Work", "Personal"]);
+        assert_eq!(import.items[1].label, "Steam:gabe");
+        assert_eq!(import.items[1].kind, Kind::Steam);
+        assert_eq!(import.items[1].digits, 5);
+        assert_eq!(import.skipped.len(), 1);
+        assert_eq!(import.skipped[0].0, "carol");
+        assert_eq!(
+            import.ignored,
+            vec![(
+                String::from("ACME Co:alice@example.com"),
+                String::from("note")
+            )]
+        );
+    }
+
+    #[test]
+    pub fn test_export_vault_round_trip() {
+        use super::*;
+        let vault = export_vault(&items(), None).unwrap().vault;
+        let import = import_vault(&vault, None).unwrap();
+
+        // Each tag is a group of its own.
+        assert!(vault.contains("\"name\": \"Code\""));
+        assert!(!vault.contains("Work,Code"));
+
+        assert_eq!(import.items, items());
+        assert!(import.skipped.is_empty());
+        assert!(import.ignored.is_empty());
+    }
+
+    #[test]
+    pub fn test_export_vault_skipped() {
+        use super::*;
+        let mut items = items();
+        items[0].t0 = 1000;
+        items[1].time_offset = 30;
+        let export = export_vault(&items, None).unwrap();
+
+        assert_eq!(export.exported, 1);
+        assert_eq!(export.skipped.len(), 1);
+        assert_eq!(export.skipped[0].0, "Example:alice");
+        assert_eq!(
+            export.ignored,
+            vec![(String::from("bob"), String::from("time offset"))]
+        );
+        items[1].time_offset = 0;
+        assert_eq!(
+            import_vault(&export.vault, None).unwrap().items,
+            vec![items[1].clone()]
+        );
+    }
+
+    #[test]
+    pub fn test_export_vault_encrypted_round_trip() {
+        use super::*;
+        let vault = export_vault_with_cost(&items(), Some("hunter2"), 4)
+            .unwrap()
+            .vault;
+
+        assert!(is_encrypted(&vault).unwrap());
+        assert!(!vault.contains("JBSWY3DPEHPK3PXP"));
+        assert!(import_vault(&vault, None).is_err());
+        assert!(import_vault(&vault, Some("wrong")).is_err());
+        assert_eq!(
+            import_vault(&vault, Some("hunter2")).unwrap().items,
+            items()
+        );
+    }
+
+    #[test]
+    pub fn test_import_vault_fail() {
+        use super::*;
+        assert!(import_vault("[]", None).is_err());
+        assert!(import_vault(
+            r#"{"version": 2, "header": {"slots": null, "params": null}, "db": {"version": 2, "entries": []}}"#,
+            None
+        )
+        .is_err());
+    }
+
+    #[test]
+    pub fn test_import_vault_expensive() {
+        use super::*;
+        let vault = export_vault_with_cost(&items(), Some("hunter2"), 4)
+            .unwrap()
+            .vault;
+
+        for (name, value) in [("n", 1u64 << 30), ("r", 1 << 20), ("p", 1 << 20)] {
+            let mut file: serde_json::Value = serde_json::from_str(&vault).unwrap();
+            file["header"]["slots"][0][name] = value.into();
+
+            assert!(import_vault(&file.to_string(), Some("hunter2"))
+                .unwrap_err()
+                .contains("more expensive"));
+        }
+    }
+}
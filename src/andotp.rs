@@ -0,0 +1,148 @@
+// Importing the plain JSON backups of andOTP. Encrypted backups aren't supported, andOTP can
+// export the accounts again without encryption.
+
+use crate::item::{Algorithm, Item, Kind};
+use crate::util::{import_label, normalize_secret, parse_tags};
+use serde::Deserialize;
+
+/// The items read from an andOTP backup, along with the entries that could not be imported.
+#[derive(Debug)]
+pub struct Import {
+    pub items: Vec<Item>,
+    pub skipped: Vec<(String, String)>,
+}
+
+#[derive(Deserialize)]
+struct Entry {
+    secret: String,
+    #[serde(default)]
+    issuer: String,
+    #[serde(default)]
+    label: String,
+    digits: u32,
+    #[serde(rename = "type")]
+    kind: String,
+    algorithm: String,
+    #[serde(default)]
+    period: Option<u32>,
+    #[serde(default)]
+    counter: Option<u64>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+/// Reads the entries of a plain andOTP backup.
+pub fn import_backup(contents: &str) -> Result<Import, String> {
+    let entries: Vec<Entry>;
+    match serde_json::from_str(contents) {
+        Ok(e) => entries = e,
+        Err(e) => {
+            return Err(format!(
+                "The file is not a plain andOTP backup, encrypted backups have to be exported \
+                 again without a password: {}",
+                e
+            ))
+        }
+    }
+
+    let mut import = Import {
+        items: Vec::new(),
+        skipped: Vec::new(),
+    };
+
+    for entry in entries.iter() {
+        let label = import_label(entry.issuer.trim(), entry.label.trim());
+
+        match entry_to_item(entry, &label) {
+            Ok(item) => import.items.push(item),
+            Err(reason) => import.skipped.push((label, reason)),
+        }
+    }
+
+    return Ok(import);
+}
+
+fn entry_to_item(entry: &Entry, label: &String) -> Result<Item, String> {
+    let kind = match entry.kind.to_lowercase().as_str() {
+        "totp" => Kind::Totp,
+        "hotp" => Kind::Hotp,
+        "steam" => Kind::Steam,
+        other => return Err(format!("{} entries are not supported", other)),
+    };
+
+    let algorithm;
+    match entry.algorithm.parse::<Algorithm>() {
+        Ok(a) => algorithm = a,
+        Err(_) => {
+            return Err(format!(
+                "the {} algorithm is not supported",
+                entry.algorithm
+            ))
+        }
+    }
+
+    let secret;
+    match normalize_secret(&entry.secret) {
+        Ok(s) => secret = s,
+        Err(_) => return Err(String::from("the secret is not a base-32 string")),
+    }
+
+    return Ok(Item {
+        label: label.clone(),
+        secret,
+        digits: entry.digits,
+        split_time: entry.period.unwrap_or(30),
+        kind,
+        counter: entry.counter.unwrap_or(0),
+        algorithm,
+        issuer: if entry.issuer.trim().is_empty() {
+            None
+        } else {
+            Some(String::from(entry.issuer.trim()))
+        },
+        account: if entry.label.trim().is_empty() {
+            None
+        } else {
+            Some(String::from(entry.label.trim()))
+        },
+        tags: parse_tags(&entry.tags.join(",")),
+        time_offset: 0,
+        t0: 0,
+    });
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    pub fn test_import_backup() {
+        use super::*;
+        let backup = r#"[
+            {"secret": "JBSWY3DPEHPK3PXP", "issuer": "Steam", "label": "gabe", "digits": 5,
+             "type": "STEAM", "algorithm": "SHA1", "thumbnail": "Steam", "last_used": 0,
+             "used_frequency": 0, "period": 30, "tags": ["Games"]},
+            {"secret": "JBSWY3DPEHPK3PXP", "issuer": "", "label": "bank", "digits": 8,
+             "type": "HOTP", "algorithm": "SHA256", "counter": 4, "tags": []},
+            {"secret": "JBSWY3DPEHPK3PXP", "issuer": "", "label": "carol", "digits": 6,
+             "type": "MOTP", "algorithm": "MD5", "period": 10, "tags": []}
+        ]"#;
+
+        let import = import_backup(backup).unwrap();
+
+        assert_eq!(import.items.len(), 2);
+        assert_eq!(import.items[0].label, "Steam:gabe");
+        assert_eq!(import.items[0].kind, Kind::Steam);
+        assert_eq!(import.items[0].account, Some(String::from("gabe")));
+        assert_eq!(import.items[0].tags, vec!["Games"]);
+        assert_eq!(import.items[1].kind, Kind::Hotp);
+        assert_eq!(import.items[1].counter, 4);
+        assert_eq!(import.items[1].algorithm, Algorithm::Sha256);
+        assert_eq!(
+            import.skipped,
+            vec![(
+                String::from("carol"),
+                String::from("motp entries are not supported")
+            )]
+        );
+        assert!(import_backup("not json").is_err());
+    }
+}
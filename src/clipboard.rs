@@ -0,0 +1,145 @@
+// Copying codes to the system clipboard. A copied code is cleared again after a timeout so that it
+// doesn't linger, but only while the clipboard still holds it, leaving anything copied since alone.
+
+use arboard::Clipboard;
+use std::time::{Duration, Instant};
+
+/// How long a code that isn't cleared is still served for. Some platforms only keep the
+/// clipboard contents while the program that set them is running.
+pub const HOLD_WITHOUT_CLEAR: Duration = Duration::from_secs(30);
+
+/// The clipboard operations a copied code needs, which tests provide without a display.
+pub trait ClipboardAccess {
+    fn get_text(&mut self) -> Option<String>;
+    fn set_text(&mut self, text: &String) -> Result<(), String>;
+    fn clear(&mut self);
+}
+
+impl ClipboardAccess for Clipboard {
+    fn get_text(&mut self) -> Option<String> {
+        return Clipboard::get_text(self).ok();
+    }
+
+    fn set_text(&mut self, text: &String) -> Result<(), String> {
+        match Clipboard::set_text(self, text.clone()) {
+            Ok(()) => return Ok(()),
+            Err(e) => return Err(format!("Could not copy to the clipboard: {}", e)),
+        }
+    }
+
+    fn clear(&mut self) {
+        let _ = Clipboard::clear(self);
+    }
+}
+
+/// A code placed on the clipboard that is cleared once its timeout has passed.
+pub struct CopiedCode<C: ClipboardAccess = Clipboard> {
+    clipboard: C,
+    code: String,
+    clear_at: Option<Instant>,
+}
+
+impl CopiedCode {
+    /// Places the code on the clipboard, to be cleared after clear_after seconds, or never if 0.
+    pub fn copy(code: &String, clear_after: u64) -> Result<CopiedCode, String> {
+        match Clipboard::new() {
+            Ok(clipboard) => return CopiedCode::copy_to(clipboard, code, clear_after),
+            Err(e) => return Err(format!("Could not open the clipboard: {}", e)),
+        }
+    }
+}
+
+impl<C: ClipboardAccess> CopiedCode<C> {
+    pub fn copy_to(mut clipboard: C, code: &String, clear_after: u64) -> Result<Self, String> {
+        clipboard.set_text(code)?;
+
+        let clear_at;
+        if clear_after == 0 {
+            clear_at = None;
+        } else {
+            clear_at = Some(Instant::now() + Duration::from_secs(clear_after));
+        }
+
+        return Ok(CopiedCode {
+            clipboard,
+            code: code.clone(),
+            clear_at,
+        });
+    }
+
+    /// The time left until the code is cleared, or None if it is kept.
+    pub fn remaining(&self) -> Option<Duration> {
+        return self
+            .clear_at
+            .map(|at| at.saturating_duration_since(Instant::now()));
+    }
+
+    /// Whether the timeout has passed and the code should be cleared.
+    #[cfg_attr(not(feature = "interactive"), allow(dead_code))]
+    pub fn is_due(&self) -> bool {
+        return self.remaining() == Some(Duration::ZERO);
+    }
+
+    /// Whether the clipboard still holds the code, rather than something copied since.
+    pub fn is_held(&mut self) -> bool {
+        return self.clipboard.get_text().as_ref() == Some(&self.code);
+    }
+
+    /// Clears the clipboard if it still holds the code.
+    pub fn clear(&mut self) {
+        if self.is_held() {
+            self.clipboard.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ClipboardAccess;
+
+    struct MockClipboard {
+        text: Option<String>,
+    }
+
+    impl ClipboardAccess for MockClipboard {
+        fn get_text(&mut self) -> Option<String> {
+            return self.text.clone();
+        }
+
+        fn set_text(&mut self, text: &String) -> Result<(), String> {
+            self.text = Some(text.clone());
+            return Ok(());
+        }
+
+        fn clear(&mut self) {
+            self.text = None;
+        }
+    }
+
+    #[test]
+    pub fn test_clear() {
+        use super::*;
+        let code = String::from("123456");
+        let mut copied = CopiedCode::copy_to(MockClipboard { text: None }, &code, 30).unwrap();
+
+        assert!(copied.is_held());
+        assert!(!copied.is_due());
+        copied.clear();
+        assert_eq!(copied.clipboard.text, None);
+    }
+
+    #[test]
+    pub fn test_clear_changed() {
+        use super::*;
+        let code = String::from("123456");
+        let mut copied = CopiedCode::copy_to(MockClipboard { text: None }, &code, 0).unwrap();
+
+        assert_eq!(copied.remaining(), None);
+
+        // Something else was copied since, which must be left alone.
+        copied.clipboard.text = Some(String::from("hunter2"));
+        assert!(!copied.is_held());
+        copied.clear();
+        assert_eq!(copied.clipboard.text, Some(String::from("hunter2")));
+    }
+}
@@ -1,10 +1,14 @@
 mod term;
+use crate::otp::Clock;
 use term::Term;
 
-pub fn run() {
+/// Runs the interactive mode. The next code of a TOTP item is shown once no more than
+/// next_code_within seconds of the current one remain, and copied codes are cleared from the
+/// clipboard after clear_after seconds. Codes are generated for the time of the clock.
+pub fn run(next_code_within: u64, clear_after: u64, clock: Clock) {
     let err: Option<&'static str>;
     {
-        let mut term = Term::new();
+        let mut term = Term::new(next_code_within, clear_after, clock);
 
         match term.start() {
             Ok(_) => err = None,
@@ -1,7 +1,10 @@
-use crate::item::{Digits, Item};
+use crate::clipboard::CopiedCode;
+use crate::item::{parse_digits, Algorithm, Item, Kind};
 use crate::item_storage;
+use crate::otp::{Clock, STEAM_CODE_LENGTH};
+use crate::uri;
 use crate::util::*;
-use arboard::Clipboard;
+use std::collections::{HashMap, HashSet};
 use std::io::{self, Write};
 use std::sync::mpsc;
 use std::sync::mpsc::Receiver;
@@ -18,7 +21,27 @@ use tui::style::{Color, Modifier, Style};
 use tui::text::{Span, Spans};
 use tui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
 use tui::Terminal;
-use unicode_width::UnicodeWidthStr;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+// The titles of the input boxes in the new and edit item menus, in the order they are displayed.
+const ITEM_FIELD_TITLES: [&'static str; 10] = [
+    "Label",
+    "Secret (Base-32)",
+    "Digits (4-10)",
+    "Period (seconds)",
+    "Type (totp/hotp/steam)",
+    "Counter (hotp)",
+    "Algorithm (sha1/sha256/sha512)",
+    "Issuer (optional)",
+    "Account (optional)",
+    "Tags (comma separated)",
+];
+
+// The heading of the items without tags when the main menu is grouped.
+const UNTAGGED_GROUP: &'static str = "Untagged";
+
+// The number of cells in the countdown bar of a TOTP item.
+const COUNTDOWN_WIDTH: usize = 10;
 
 #[derive(PartialEq)]
 enum TermMenu {
@@ -28,6 +51,14 @@ enum TermMenu {
     None,
 }
 
+/// A line of the main menu, which groups the items by tag once any item is tagged.
+#[derive(PartialEq)]
+enum MainRow {
+    /// The heading of the items with a tag, or of the untagged items when None.
+    Group(Option<String>),
+    Item(usize),
+}
+
 #[derive(Clone)]
 enum Status {
     None,
@@ -38,6 +69,7 @@ enum Status {
 pub struct Term {
     terminal: Terminal<TermionBackend<RawTerminal<io::Stdout>>>,
     items: Vec<Item>,
+    passphrase: String,
     current_menu: TermMenu,
     selected_index: usize,
     copy_status: Status,
@@ -47,12 +79,73 @@ pub struct Term {
     item_secret: Option<String>,
     item_digits: Option<String>,
     item_period: Option<String>,
+    item_kind: Option<String>,
+    item_counter: Option<String>,
+    item_algorithm: Option<String>,
+    item_issuer: Option<String>,
+    item_account: Option<String>,
+    item_tags: Option<String>,
+    hotp_codes: HashMap<String, String>,
     field_cursor_x: u16,
     pending_confirmation: bool,
+    next_code_within: u64,
+    save_failed_before_quit: bool,
+    // The items as they were last loaded or saved, and a hash of the database file at that time.
+    // These are used to notice and merge changes made by other otpc processes.
+    base_items: Vec<Item>,
+    fingerprint: Option<Vec<u8>>,
+    // The groups of the main menu whose items are hidden, by lowercase tag.
+    collapsed_groups: HashSet<Option<String>>,
+    // The query typed after pressing '/', which filters the main menu while it is set.
+    search: Option<String>,
+    // Copied codes are cleared from the clipboard after this many seconds, unless it is 0.
+    clear_after: u64,
+    copied: Option<CopiedCode>,
+    clock: Clock,
 }
 
 impl Term {
-    pub fn new() -> Term {
+    pub fn new(next_code_within: u64, clear_after: u64, clock: Clock) -> Term {
+        // The passphrase has to be read before the terminal is switched into raw mode.
+        let passphrase;
+        match request_passphrase(!item_storage::is_encrypted(
+            &item_storage::storage_location(),
+        )) {
+            Ok(p) => passphrase = p,
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+
+        let path = item_storage::storage_location();
+        let items: Vec<Item>;
+        let fingerprint;
+        {
+            let _lock;
+            match item_storage::lock_database(&path) {
+                Ok(l) => _lock = l,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+
+            if item_storage::storage_location_exists() {
+                match item_storage::retrieve_items(&path, &passphrase) {
+                    Ok(i) => items = i,
+                    Err(e) => {
+                        eprintln!("An error occurred when reading the database: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+            } else {
+                items = Vec::new();
+            }
+
+            fingerprint = item_storage::fingerprint(&path);
+        }
+
         let backend;
         match io::stdout().into_raw_mode() {
             Ok(out) => backend = TermionBackend::new(out),
@@ -72,23 +165,17 @@ impl Term {
             }
         }
 
-        let items: Vec<Item>;
-
-        if item_storage::storage_location_exists() {
-            match item_storage::retrieve_items(&item_storage::storage_location()) {
-                Ok(i) => items = i,
-                Err(e) => {
-                    eprintln!("An error occurred when reading the database: {}", e);
-                    std::process::exit(1);
-                }
-            }
-        } else {
-            items = Vec::new();
-        }
-
         return Term {
             terminal,
+            base_items: items.clone(),
             items,
+            fingerprint,
+            collapsed_groups: HashSet::new(),
+            search: None,
+            clear_after,
+            copied: None,
+            clock,
+            passphrase,
             current_menu: TermMenu::None,
             selected_index: 0,
             copy_status: Status::None,
@@ -98,8 +185,17 @@ impl Term {
             item_secret: None,
             item_digits: None,
             item_period: None,
+            item_kind: None,
+            item_counter: None,
+            item_algorithm: None,
+            item_issuer: None,
+            item_account: None,
+            item_tags: None,
+            hotp_codes: HashMap::new(),
             field_cursor_x: 0,
             pending_confirmation: false,
+            next_code_within,
+            save_failed_before_quit: false,
         };
     }
 
@@ -119,7 +215,16 @@ impl Term {
         let receiver = Term::spawn_stdin_channel();
 
         loop {
-            self.draw_menu(&receiver)?;
+            if self.copied.as_ref().is_some_and(|c| c.is_due()) {
+                if let Some(mut copied) = self.copied.take() {
+                    copied.clear();
+                }
+            }
+
+            if let Err(e) = self.draw_menu(&receiver) {
+                self.clear_copied();
+                return Err(e);
+            }
         }
     }
 
@@ -132,6 +237,12 @@ impl Term {
                 self.item_secret = None;
                 self.item_digits = None;
                 self.item_period = None;
+                self.item_kind = None;
+                self.item_counter = None;
+                self.item_algorithm = None;
+                self.item_issuer = None;
+                self.item_account = None;
+                self.item_tags = None;
                 let _ = self.terminal.hide_cursor();
                 self.field_cursor_x = 0;
             }
@@ -140,15 +251,23 @@ impl Term {
 
         match new_menu {
             TermMenu::Edit => {
-                self.editing_item_index = Some(self.selected_index);
-                self.item_label = Some(self.items[self.selected_index].label.clone());
-                self.item_secret = Some(self.items[self.selected_index].secret.clone());
-                self.item_digits = match self.items[self.selected_index].digits {
-                    Digits::Eight => Some(String::from("8")),
-                    Digits::Seven => Some(String::from("7")),
-                    Digits::Six => Some(String::from("6")),
-                };
-                self.item_period = Some(format!("{}", self.items[self.selected_index].split_time));
+                let index;
+                match self.selected_item() {
+                    Some(i) => index = i,
+                    None => return,
+                }
+
+                self.editing_item_index = Some(index);
+                self.item_label = Some(self.items[index].label.clone());
+                self.item_secret = Some(self.items[index].secret.clone());
+                self.item_digits = Some(self.items[index].digits.to_string());
+                self.item_period = Some(format!("{}", self.items[index].split_time));
+                self.item_kind = Some(self.items[index].kind.to_string());
+                self.item_counter = Some(format!("{}", self.items[index].counter));
+                self.item_algorithm = Some(self.items[index].algorithm.to_string().to_lowercase());
+                self.item_issuer = self.items[index].issuer.clone();
+                self.item_account = self.items[index].account.clone();
+                self.item_tags = Some(self.items[index].tags.join(", "));
 
                 self.selected_index = 0;
                 let _ = self.terminal.show_cursor();
@@ -156,6 +275,9 @@ impl Term {
             TermMenu::New => {
                 self.selected_index = 0;
                 self.editing_item_index = None;
+                self.item_kind = Some(Kind::Totp.to_string());
+                self.item_counter = Some(String::from("0"));
+                self.item_algorithm = Some(Algorithm::Sha1.to_string().to_lowercase());
                 let _ = self.terminal.show_cursor();
             }
             TermMenu::Main => {
@@ -237,56 +359,47 @@ impl Term {
         &mut self,
         receiver: &Receiver<Result<termion::event::Key, std::io::Error>>,
     ) -> Result<(), &'static str> {
-        match Term::get_key(receiver)? {
+        let last_index = ITEM_FIELD_TITLES.len() - 1;
+
+        match self.get_key(receiver)? {
             Some(k) => match k {
                 Key::Char(c) => {
                     self.reset_changing_fields();
 
-                    if !c.is_whitespace() {
-                        if self.selected_index == 0 {
-                            self.item_label
-                                .get_or_insert(String::new())
-                                .insert(self.field_cursor_x as usize, c);
-                            self.field_cursor_x += 1;
-                        } else if self.selected_index == 1 {
-                            if is_base_32_c(c) {
-                                self.item_secret
-                                    .get_or_insert(String::new())
-                                    .insert(self.field_cursor_x as usize, c);
-                                self.field_cursor_x += 1;
-                            }
-                        } else if self.selected_index == 2 {
-                            if c == '6' || c == '7' || c == '8' {
-                                self.item_digits
-                                    .get_or_insert(String::new())
-                                    .insert(self.field_cursor_x as usize, c);
-                                self.field_cursor_x += 1;
-                            }
-                        } else if self.selected_index == 3 {
-                            if c.is_numeric() {
-                                self.item_period
-                                    .get_or_insert(String::new())
-                                    .insert(self.field_cursor_x as usize, c);
-                                self.field_cursor_x += 1;
-                            }
+                    if c != '\n' {
+                        if Term::item_field_accepts(self.selected_index, c) {
+                            let x = self.field_cursor_x;
+                            self.field_cursor_x = insert_at_column(
+                                self.item_field_mut(self.selected_index)
+                                    .get_or_insert(String::new()),
+                                x,
+                                c,
+                            );
                         }
-                    } else if c == '\n' {
+                    } else {
                         self.reset_changing_fields();
 
-                        if self.selected_index < 3 {
+                        let pasted_uri = self.selected_index == 0
+                            && self
+                                .item_label
+                                .as_ref()
+                                .is_some_and(|s| s.starts_with("otpauth://"));
+
+                        if pasted_uri {
+                            self.item_menu_fill_from_uri();
+                        } else if self.selected_index < last_index {
                             self.selected_index += 1;
                             self.item_menu_check_x();
                         } else {
                             if self.current_menu == TermMenu::New {
-                                if self.new_menu_add_item() {
-                                    self.save()?;
-                                    self.switch_menu(TermMenu::Main);
-                                }
-                            } else if self.current_menu == TermMenu::Edit {
-                                if self.edit_menu_save_item() {
-                                    self.save()?;
+                                if self.new_menu_add_item() && self.save() {
                                     self.switch_menu(TermMenu::Main);
                                 }
+                            } else if self.current_menu == TermMenu::Edit
+                                && self.edit_menu_save_item()
+                                && self.save()
+                            {
+                                self.switch_menu(TermMenu::Main);
                             }
                         }
                     }
@@ -294,31 +407,12 @@ impl Term {
                 Key::Backspace => {
                     self.reset_changing_fields();
 
-                    if self.selected_index == 0 {
-                        let str = self.item_label.get_or_insert(String::new());
-                        if str.len() > 0 && self.field_cursor_x < (str.len() as u16 + 1) {
-                            str.remove((self.field_cursor_x as usize) - 1);
-                            self.field_cursor_x -= 1;
-                        }
-                    } else if self.selected_index == 1 {
-                        let str = self.item_secret.get_or_insert(String::new());
-                        if str.len() > 0 && self.field_cursor_x < (str.len() as u16 + 1) {
-                            str.remove((self.field_cursor_x as usize) - 1);
-                            self.field_cursor_x -= 1;
-                        }
-                    } else if self.selected_index == 2 {
-                        let str = self.item_digits.get_or_insert(String::new());
-                        if str.len() > 0 && self.field_cursor_x < (str.len() as u16 + 1) {
-                            str.remove((self.field_cursor_x as usize) - 1);
-                            self.field_cursor_x -= 1;
-                        }
-                    } else if self.selected_index == 3 {
-                        let str = self.item_period.get_or_insert(String::new());
-                        if str.len() > 0 && self.field_cursor_x < (str.len() as u16 + 1) {
-                            str.remove((self.field_cursor_x as usize) - 1);
-                            self.field_cursor_x -= 1;
-                        }
-                    }
+                    let x = self.field_cursor_x;
+                    self.field_cursor_x = remove_before_column(
+                        self.item_field_mut(self.selected_index)
+                            .get_or_insert(String::new()),
+                        x,
+                    );
                 }
                 Key::Esc => {
                     self.reset_changing_fields();
@@ -330,7 +424,7 @@ impl Term {
                     if self.selected_index != 0 {
                         self.selected_index -= 1;
                     } else {
-                        self.selected_index = 3;
+                        self.selected_index = last_index;
                     }
 
                     self.item_menu_check_x();
@@ -338,7 +432,7 @@ impl Term {
                 Key::Down => {
                     self.reset_changing_fields();
 
-                    if self.selected_index == 3 {
+                    if self.selected_index == last_index {
                         self.selected_index = 0;
                     } else {
                         self.selected_index += 1;
@@ -349,107 +443,31 @@ impl Term {
                 Key::Right => {
                     self.reset_changing_fields();
 
-                    if self.selected_index == 0 {
-                        match &self.item_label {
-                            Some(s) => {
-                                // <= because we want the cursor to sit one cell past the last character
-                                if self.field_cursor_x + 1 <= s.width() as u16 {
-                                    self.field_cursor_x += 1;
-                                } else {
-                                    self.field_cursor_x = 0;
-                                }
-                            }
-                            None => self.field_cursor_x = 0,
-                        }
-                    } else if self.selected_index == 1 {
-                        match &self.item_secret {
-                            Some(s) => {
-                                // <= because we want the cursor to sit one cell past the last character
-                                if self.field_cursor_x + 1 <= s.width() as u16 {
-                                    self.field_cursor_x += 1;
-                                } else {
-                                    self.field_cursor_x = 0;
-                                }
-                            }
-                            None => self.field_cursor_x = 0,
-                        }
-                    } else if self.selected_index == 2 {
-                        match &self.item_digits {
-                            Some(s) => {
-                                // <= because we want the cursor to sit one cell past the last character
-                                if self.field_cursor_x + 1 <= s.width() as u16 {
-                                    self.field_cursor_x += 1;
-                                } else {
-                                    self.field_cursor_x = 0;
-                                }
-                            }
-                            None => self.field_cursor_x = 0,
-                        }
-                    } else if self.selected_index == 3 {
-                        match &self.item_period {
-                            Some(s) => {
-                                // <= because we want the cursor to sit one cell past the last character
-                                if self.field_cursor_x + 1 <= s.width() as u16 {
-                                    self.field_cursor_x += 1;
-                                } else {
-                                    self.field_cursor_x = 0;
-                                }
+                    match self.item_field(self.selected_index) {
+                        Some(s) => {
+                            // Allow the cursor to sit one cell past the last character
+                            if self.field_cursor_x < s.width() as u16 {
+                                self.field_cursor_x += 1;
+                            } else {
+                                self.field_cursor_x = 0;
                             }
-                            None => self.field_cursor_x = 0,
                         }
+                        None => self.field_cursor_x = 0,
                     }
                 }
                 Key::Left => {
                     self.reset_changing_fields();
 
-                    if self.selected_index == 0 {
-                        match &self.item_label {
-                            Some(s) => {
-                                // Check if greater than 1 because any subtraction to field_cursor_x will cause integer overflow.
-                                if self.field_cursor_x > 1 {
-                                    self.field_cursor_x -= 1;
-                                } else {
-                                    self.field_cursor_x = s.width() as u16;
-                                }
-                            }
-                            None => self.field_cursor_x = 0,
-                        }
-                    } else if self.selected_index == 1 {
-                        match &self.item_secret {
-                            Some(s) => {
-                                // Check if greater than 1 because any subtraction to field_cursor_x will cause integer overflow.
-                                if self.field_cursor_x > 1 {
-                                    self.field_cursor_x -= 1;
-                                } else {
-                                    self.field_cursor_x = s.width() as u16;
-                                }
-                            }
-                            None => self.field_cursor_x = 0,
-                        }
-                    } else if self.selected_index == 2 {
-                        match &self.item_digits {
-                            Some(s) => {
-                                // Check if greater than 1 because any subtraction to field_cursor_x will cause integer overflow.
-                                if self.field_cursor_x > 1 {
-                                    self.field_cursor_x -= 1;
-                                } else {
-                                    self.field_cursor_x = s.width() as u16;
-                                }
-                            }
-                            None => self.field_cursor_x = 0,
-                        }
-                    } else if self.selected_index == 3 {
-                        match &self.item_period {
-                            Some(s) => {
-                                // Check if greater than 1 because any subtraction to field_cursor_x will cause integer overflow.
-                                if self.field_cursor_x > 1 {
-                                    self.field_cursor_x -= 1;
-                                } else {
-                                    self.field_cursor_x = s.width() as u16;
-                                }
+                    match self.item_field(self.selected_index) {
+                        Some(s) => {
+                            // Check if greater than 1 because any subtraction to field_cursor_x will cause integer overflow.
+                            if self.field_cursor_x > 1 {
+                                self.field_cursor_x -= 1;
+                            } else {
+                                self.field_cursor_x = s.width() as u16;
                             }
-                            None => self.field_cursor_x = 0,
                         }
+                        None => self.field_cursor_x = 0,
                     }
                 }
                 _ => (),
@@ -460,46 +478,61 @@ impl Term {
         return Ok(());
     }
 
-    fn draw_edit_menu(
-        &mut self,
-        completion_text: &'static str,
-        title: &'static str,
-    ) -> Result<(), &'static str> {
-        let label_input;
-
-        match self.item_label {
-            Some(ref s) => label_input = Spans::from(vec![Span::raw(s)]),
-            None => {
-                label_input = Spans::from(vec![Span::raw(String::new())]);
-                self.item_label = Some(String::new());
-            }
+    fn item_field(&self, index: usize) -> &Option<String> {
+        match index {
+            0 => return &self.item_label,
+            1 => return &self.item_secret,
+            2 => return &self.item_digits,
+            3 => return &self.item_period,
+            4 => return &self.item_kind,
+            5 => return &self.item_counter,
+            6 => return &self.item_algorithm,
+            7 => return &self.item_issuer,
+            8 => return &self.item_account,
+            _ => return &self.item_tags,
         }
+    }
 
-        let secret_input;
-        match self.item_secret {
-            Some(ref s) => secret_input = Spans::from(vec![Span::raw(s)]),
-            None => {
-                secret_input = Spans::from(vec![Span::raw(String::new())]);
-                self.item_secret = Some(String::new());
-            }
+    fn item_field_mut(&mut self, index: usize) -> &mut Option<String> {
+        match index {
+            0 => return &mut self.item_label,
+            1 => return &mut self.item_secret,
+            2 => return &mut self.item_digits,
+            3 => return &mut self.item_period,
+            4 => return &mut self.item_kind,
+            5 => return &mut self.item_counter,
+            6 => return &mut self.item_algorithm,
+            7 => return &mut self.item_issuer,
+            8 => return &mut self.item_account,
+            _ => return &mut self.item_tags,
         }
+    }
 
-        let digits_input;
-        match self.item_digits {
-            Some(ref s) => digits_input = Spans::from(vec![Span::raw(s)]),
-            None => {
-                digits_input = Spans::from(vec![Span::raw(String::new())]);
-                self.item_digits = Some(String::new());
-            }
+    /// Filters the characters that can be typed into each input box.
+    fn item_field_accepts(index: usize, c: char) -> bool {
+        match index {
+            // Characters that take up no cells would leave the cursor out of step with the text.
+            0 | 7 | 8 | 9 => return c == ' ' || (!c.is_whitespace() && c.width().unwrap_or(0) > 0),
+            1 => return is_secret_c(c),
+            4 => return c.is_ascii_alphabetic(),
+            6 => return c.is_ascii_alphanumeric() || c == '-',
+            _ => return c.is_numeric(),
         }
+    }
 
-        let period_input;
-        match self.item_period {
-            Some(ref s) => period_input = Spans::from(vec![Span::raw(s)]),
-            None => {
-                period_input = Spans::from(vec![Span::raw(String::new())]);
-                self.item_period = Some(String::new());
-            }
+    fn draw_edit_menu(
+        &mut self,
+        completion_text: &'static str,
+        title: &'static str,
+    ) -> Result<(), &'static str> {
+        let mut inputs = Vec::new();
+
+        for index in 0..ITEM_FIELD_TITLES.len() {
+            inputs.push(Spans::from(vec![Span::raw(
+                self.item_field_mut(index)
+                    .get_or_insert(String::new())
+                    .clone(),
+            )]));
         }
 
         let selected_index = self.selected_index;
@@ -517,64 +550,31 @@ impl Term {
                 root_chunks[0],
             );
 
+            let mut constraints = vec![Constraint::Length(3); ITEM_FIELD_TITLES.len()];
+            constraints.push(Constraint::Min(0));
+
             let vert_chunks = Layout::default()
                 .direction(Direction::Vertical)
                 .margin(2)
-                .constraints(
-                    [
-                        Constraint::Length(3),
-                        Constraint::Length(3),
-                        Constraint::Length(3),
-                        Constraint::Length(3),
-                        Constraint::Min(0),
-                    ]
-                    .as_ref(),
-                )
+                .constraints(constraints)
                 .split(root_chunks[0]);
 
-            f.render_widget(
-                Paragraph::new(label_input)
-                    .block(Block::default().borders(Borders::ALL).title("Label"))
-                    .alignment(Alignment::Left),
-                vert_chunks[0],
-            );
-
-            f.render_widget(
-                Paragraph::new(secret_input)
-                    .block(
-                        Block::default()
-                            .borders(Borders::ALL)
-                            .title("Secret (Base-32)"),
-                    )
-                    .alignment(Alignment::Left),
-                vert_chunks[1],
-            );
-
-            f.render_widget(
-                Paragraph::new(digits_input)
-                    .block(
-                        Block::default()
-                            .borders(Borders::ALL)
-                            .title("Digits (6/7/8)"),
-                    )
-                    .alignment(Alignment::Left),
-                vert_chunks[2],
-            );
-
-            f.render_widget(
-                Paragraph::new(period_input)
-                    .block(
-                        Block::default()
-                            .borders(Borders::ALL)
-                            .title("Period (seconds)"),
-                    )
-                    .alignment(Alignment::Left),
-                vert_chunks[3],
-            );
+            for (index, input) in inputs.into_iter().enumerate() {
+                f.render_widget(
+                    Paragraph::new(input)
+                        .block(
+                            Block::default()
+                                .borders(Borders::ALL)
+                                .title(ITEM_FIELD_TITLES[index]),
+                        )
+                        .alignment(Alignment::Left),
+                    vert_chunks[index],
+                );
+            }
 
             let text = if alternate_footer.is_empty() {
                 Spans::from(vec![
-                    if selected_index == 3 {
+                    if selected_index == ITEM_FIELD_TITLES.len() - 1 {
                         Span::raw(completion_text)
                     } else {
                         Span::raw("Enter - Next      ")
@@ -600,7 +600,7 @@ impl Term {
     }
 
     fn edit_menu_save_item(&mut self) -> bool {
-        match self.item_menu_construct_item(true) {
+        match self.item_menu_construct_item(self.editing_item_index) {
             Ok(item) => match self.editing_item_index {
                 Some(index) => {
                     if index >= self.items.len() {
@@ -608,6 +608,7 @@ impl Term {
                             String::from("Internal error obtaining the correct item.");
                         return false;
                     } else {
+                        self.hotp_codes.remove(&self.items[index].label);
                         self.items[index] = item;
                         return true;
                     }
@@ -626,7 +627,7 @@ impl Term {
     }
 
     fn new_menu_add_item(&mut self) -> bool {
-        match self.item_menu_construct_item(false) {
+        match self.item_menu_construct_item(None) {
             Ok(item) => self.items.push(item),
             Err(str) => {
                 self.alternate_footer = str;
@@ -637,30 +638,33 @@ impl Term {
         return true;
     }
 
-    fn item_menu_construct_item(&mut self, allow_same_name: bool) -> Result<Item, String> {
+    /// Builds an item from the form. The item being edited, if any, may keep its own label.
+    fn item_menu_construct_item(&mut self, editing_index: Option<usize>) -> Result<Item, String> {
         let label: String;
         let secret: String;
-        let digits: Digits;
+        let digits: u32;
         let period: u32;
+        let kind: Kind;
+        let counter: u64;
+        let algorithm: Algorithm;
+        let issuer: Option<String>;
+        let account: Option<String>;
+        let tags: Vec<String>;
 
         // Check that all fields have been filled out with valid types.
         match &self.item_label {
             Some(s) => {
-                if s.is_empty() {
-                    return Err(String::from("A label is required."));
-                }
+                label = String::from(s.trim());
 
-                if contains_white_space(&String::from(s.trim())) {
-                    return Err(String::from("No whitespace is permitted in the label."));
-                }
+                let taken = self
+                    .items
+                    .iter()
+                    .enumerate()
+                    .any(|(index, item)| item.label == label && Some(index) != editing_index);
 
-                if !allow_same_name {
-                    if contains_item_label(&s, &self.items) {
-                        return Err(String::from("An item with this label already exists."));
-                    }
+                if taken {
+                    return Err(String::from("An item with this label already exists."));
                 }
-
-                label = s.clone();
             }
             None => {
                 return Err(String::from("A label is required."));
@@ -668,17 +672,7 @@ impl Term {
         }
 
         match &self.item_secret {
-            Some(s) => {
-                if s.is_empty() {
-                    return Err(String::from("A valid base-32 secret is required."));
-                }
-
-                if !is_base_32(s) {
-                    return Err(String::from("A valid base-32 secret is required."));
-                }
-
-                secret = s.clone();
-            }
+            Some(s) => secret = normalize_secret(s)?,
             None => {
                 return Err(String::from("A valid base-32 secret is required."));
             }
@@ -690,15 +684,7 @@ impl Term {
                     return Err(String::from("A valid number of digits is required."));
                 }
 
-                if s == "6" {
-                    digits = Digits::Six;
-                } else if s == "7" {
-                    digits = Digits::Seven;
-                } else if s == "8" {
-                    digits = Digits::Eight;
-                } else {
-                    return Err(String::from("A valid number of digits is required."));
-                }
+                digits = parse_digits(s)?;
             }
             None => {
                 return Err(String::from("A valid number of digits is required."));
@@ -711,7 +697,7 @@ impl Term {
                     return Err(String::from("A valid period is required."));
                 }
 
-                if !is_number(&s) {
+                if !is_number(s) {
                     return Err(String::from("A valid period is required."));
                 }
 
@@ -721,61 +707,123 @@ impl Term {
                         return Err(String::from("A valid period is required."));
                     }
                 }
+            }
+            None => {
+                return Err(String::from("A valid period is required."));
+            }
+        }
 
-                if period == 0 {
-                    return Err(String::from("A valid period greater than 0 is required."));
+        match &self.item_kind {
+            Some(s) => match s.parse::<Kind>() {
+                Ok(k) => kind = k,
+                Err(e) => return Err(e),
+            },
+            None => {
+                return Err(String::from("A valid type is required."));
+            }
+        }
+
+        match &self.item_counter {
+            Some(s) => match s.parse::<u64>() {
+                Ok(n) => counter = n,
+                Err(_) => {
+                    return Err(String::from("A valid counter is required."));
                 }
+            },
+            None => {
+                return Err(String::from("A valid counter is required."));
             }
+        }
+
+        match &self.item_algorithm {
+            Some(s) => match s.parse::<Algorithm>() {
+                Ok(a) => algorithm = a,
+                Err(e) => return Err(e),
+            },
             None => {
-                return Err(String::from("A valid period is required."));
+                return Err(String::from("A valid algorithm is required."));
             }
         }
 
-        return Ok(Item {
+        match &self.item_issuer {
+            Some(s) if !s.trim().is_empty() => issuer = Some(String::from(s.trim())),
+            _ => issuer = None,
+        }
+
+        match &self.item_account {
+            Some(s) if !s.trim().is_empty() => account = Some(String::from(s.trim())),
+            _ => account = None,
+        }
+
+        match &self.item_tags {
+            Some(s) => tags = parse_tags(s),
+            None => tags = Vec::new(),
+        }
+
+        let item = Item {
             label,
             secret,
-            digits,
+            // Steam Guard codes always have the same length, whatever was entered.
+            digits: if kind == Kind::Steam {
+                STEAM_CODE_LENGTH
+            } else {
+                digits
+            },
             split_time: period,
-        });
+            kind,
+            counter,
+            algorithm,
+            issuer,
+            account,
+            tags,
+            // The offset and T0 aren't part of the form, editing keeps those of the item.
+            time_offset: match self.editing_item_index {
+                Some(index) => self.items[index].time_offset,
+                None => 0,
+            },
+            t0: match self.editing_item_index {
+                Some(index) => self.items[index].t0,
+                None => 0,
+            },
+        };
+
+        item.validate()?;
+
+        return Ok(item);
     }
 
-    fn item_menu_check_x(&mut self) {
-        if self.selected_index == 0 {
-            match &self.item_label {
-                Some(s) => {
-                    if self.field_cursor_x > s.width() as u16 {
-                        self.field_cursor_x = s.width() as u16;
-                    }
-                }
-                None => self.field_cursor_x = 0,
-            }
-        } else if self.selected_index == 1 {
-            match &self.item_secret {
-                Some(s) => {
-                    if self.field_cursor_x > s.width() as u16 {
-                        self.field_cursor_x = s.width() as u16;
-                    }
-                }
-                None => self.field_cursor_x = 0,
-            }
-        } else if self.selected_index == 2 {
-            match &self.item_digits {
-                Some(s) => {
-                    if self.field_cursor_x > s.width() as u16 {
-                        self.field_cursor_x = s.width() as u16;
-                    }
-                }
-                None => self.field_cursor_x = 0,
+    /// Replaces the contents of the form with the fields of an otpauth:// URI pasted into the label.
+    fn item_menu_fill_from_uri(&mut self) {
+        let item;
+        match uri::parse_uri(self.item_label.as_ref().unwrap()) {
+            Ok(i) => item = i,
+            Err(e) => {
+                self.alternate_footer = e;
+                return;
             }
-        } else if self.selected_index == 3 {
-            match &self.item_period {
-                Some(s) => {
-                    if self.field_cursor_x > s.width() as u16 {
-                        self.field_cursor_x = s.width() as u16;
-                    }
+        }
+
+        self.item_label = Some(item.label);
+        self.item_secret = Some(item.secret);
+        self.item_digits = Some(item.digits.to_string());
+        self.item_period = Some(format!("{}", item.split_time));
+        self.item_kind = Some(item.kind.to_string());
+        self.item_counter = Some(format!("{}", item.counter));
+        self.item_algorithm = Some(item.algorithm.to_string().to_lowercase());
+        self.item_issuer = item.issuer;
+        self.item_account = item.account;
+        self.item_tags = Some(item.tags.join(", "));
+        self.item_menu_check_x();
+    }
+
+    fn item_menu_check_x(&mut self) {
+        match self.item_field(self.selected_index) {
+            Some(s) => {
+                if self.field_cursor_x > s.width() as u16 {
+                    self.field_cursor_x = s.width() as u16;
                 }
-                None => self.field_cursor_x = 0,
             }
+            None => self.field_cursor_x = 0,
         }
     }
 
@@ -783,18 +831,28 @@ impl Term {
         &mut self,
         receiver: &Receiver<Result<termion::event::Key, std::io::Error>>,
     ) -> Result<(), &'static str> {
+        if !self.pending_confirmation {
+            self.reload_if_changed();
+        }
+
         self.draw_main_menu()?;
 
-        match Term::get_key(receiver)? {
+        if self.search.is_some() {
+            return self.search_menu(receiver);
+        }
+
+        match self.get_key(receiver)? {
             Some(k) => match k {
                 Key::Char(c) => {
                     if !self.pending_confirmation {
                         if c == 'q' {
                             self.quit();
                         } else if c == 'c' {
-                            self.copy();
+                            self.copy()?;
+                        } else if c == 'g' {
+                            self.generate()?;
                         } else if c == 'r' {
-                            if self.items.len() > 0 {
+                            if !self.items.is_empty() {
                                 self.alternate_footer = String::from("y - Delete      n - Cancel");
                                 self.pending_confirmation = true;
                             }
@@ -802,6 +860,11 @@ impl Term {
                             self.switch_menu(TermMenu::New);
                         } else if c == 'e' {
                             self.switch_menu(TermMenu::Edit);
+                        } else if c == '\n' || c == ' ' {
+                            self.toggle_group();
+                        } else if c == '/' {
+                            self.search = Some(String::new());
+                            self.selected_index = 0;
                         }
                     } else {
                         if c == 'y' {
@@ -811,24 +874,42 @@ impl Term {
                         }
                     }
                 }
-                Key::Up => {
-                    self.reset_changing_fields();
+                Key::Up => self.move_selection(true),
+                Key::Down => self.move_selection(false),
+                _ => (),
+            },
+            None => (),
+        }
 
-                    if self.selected_index != 0 {
-                        self.selected_index -= 1;
-                    } else {
-                        self.selected_index = self.items.len() - 1;
-                    }
+        return Ok(());
+    }
+
+    /// Handles the keys of the main menu while searching. Typed characters go to the query, so
+    /// the usual single key commands are unavailable until the search is closed.
+    fn search_menu(
+        &mut self,
+        receiver: &Receiver<Result<termion::event::Key, std::io::Error>>,
+    ) -> Result<(), &'static str> {
+        match self.get_key(receiver)? {
+            Some(k) => match k {
+                Key::Char('\n') => self.copy()?,
+                Key::Char(c) => {
+                    self.reset_changing_fields();
+                    self.search.get_or_insert_with(String::new).push(c);
+                    self.selected_index = 0;
                 }
-                Key::Down => {
+                Key::Backspace => {
                     self.reset_changing_fields();
-
-                    if self.selected_index == self.items.len() - 1 {
-                        self.selected_index = 0;
-                    } else {
-                        self.selected_index += 1;
-                    }
+                    self.search.get_or_insert_with(String::new).pop();
+                    self.selected_index = 0;
+                }
+                Key::Esc => {
+                    self.reset_changing_fields();
+                    self.search = None;
+                    self.selected_index = 0;
                 }
+                Key::Up => self.move_selection(true),
+                Key::Down => self.move_selection(false),
                 _ => (),
             },
             None => (),
@@ -837,22 +918,117 @@ impl Term {
         return Ok(());
     }
 
+    fn move_selection(&mut self, up: bool) {
+        self.reset_changing_fields();
+        let row_count = self.main_rows().len();
+
+        if up {
+            if self.selected_index != 0 {
+                self.selected_index -= 1;
+            } else {
+                self.selected_index = row_count.saturating_sub(1);
+            }
+        } else if self.selected_index + 1 >= row_count {
+            self.selected_index = 0;
+        } else {
+            self.selected_index += 1;
+        }
+    }
+
     fn draw_main_menu(&mut self) -> Result<(), &'static str> {
         let mut items: Vec<ListItem> = Vec::new();
+        let time = self.clock.now().unwrap_or(0);
+        let rows = self.main_rows();
+        let grouped = rows.iter().any(|row| matches!(row, MainRow::Group(_)));
+
+        for row in rows.iter() {
+            let item;
+            match row {
+                MainRow::Item(index) => item = self.items[*index].clone(),
+                MainRow::Group(tag) => {
+                    let count = self.items.iter().filter(|i| Term::in_group(i, tag)).count();
+                    let marker = if self.collapsed_groups.contains(&Term::group_key(tag)) {
+                        "▸"
+                    } else {
+                        "▾"
+                    };
+
+                    items.push(ListItem::new(Spans::from(vec![Span::styled(
+                        format!(
+                            "{} {} ({})",
+                            marker,
+                            tag.as_deref().unwrap_or(UNTAGGED_GROUP),
+                            count
+                        ),
+                        Style::default()
+                            .fg(Color::Cyan)
+                            .add_modifier(Modifier::BOLD),
+                    )])));
+
+                    continue;
+                }
+            }
+
+            let indent = if grouped { "  " } else { "" };
+            let algorithm = if item.algorithm == Algorithm::Sha1 {
+                String::new()
+            } else {
+                format!(" ({})", item.algorithm)
+            };
+
+            let name = Term::item_name(&item);
+            let mut spans = vec![Span::raw(indent)];
+
+            // Highlight the characters that the search query matched.
+            match self.search_match(&item) {
+                Some((_, positions)) if self.search.is_some() => {
+                    spans.extend(Term::highlighted(&name, &positions))
+                }
+                _ => spans.push(Span::raw(name)),
+            }
+
+            if item.kind == Kind::Hotp {
+                // HOTP codes are only shown once they have been generated, as that uses up the counter.
+                spans.push(Span::raw(match self.hotp_codes.get(&item.label) {
+                    Some(code) => format!(" - {} (counter {}){}", code, item.counter, algorithm),
+                    None => format!(" - HOTP (counter {}){}", item.counter, algorithm),
+                }));
+                items.push(ListItem::new(Spans::from(spans)));
+
+                continue;
+            }
 
-        for item in self.items.clone() {
             let code_string: String;
-            match item.get_code() {
+            match item.get_code_at(time) {
                 Ok(code) => code_string = code,
                 Err(_) => code_string = String::from("Error"), // Simple announcement because we don't want a long description overflowing the display.
             }
 
-            items.push(ListItem::new(format!("{} - {}", item.label, code_string)));
+            let remaining = item.remaining_seconds_at(time).unwrap_or(0);
+            let expiring = remaining <= self.next_code_within;
+            spans.extend(vec![
+                Span::raw(format!(" - {}  ", code_string)),
+                Span::styled(
+                    Term::countdown_bar(remaining, item.split_time as u64),
+                    Style::default().fg(if expiring { Color::Red } else { Color::Green }),
+                ),
+                Span::raw(format!(" {:>3}s{}", remaining, algorithm)),
+            ]);
+
+            // Show the upcoming code early so that it can be used instead of one about to expire.
+            if expiring {
+                if let Ok(next) = item.next_code_at(time) {
+                    spans.push(Span::raw(format!("  next {}", next)));
+                }
+            }
+
+            items.push(ListItem::new(Spans::from(spans)));
         }
 
         let copy_status = self.copy_status.clone();
-        let selected_index = self.selected_index.clone();
+        let selected_index = self.selected_index;
         let alternate_footer = self.alternate_footer.clone();
+        let search = self.search.clone();
         let mut current_state = ListState::default();
 
         current_state.select(Some(selected_index));
@@ -872,7 +1048,11 @@ impl Term {
                             .borders(
                                 Borders::TOP | Borders::RIGHT | Borders::LEFT | Borders::BOTTOM,
                             )
-                            .title("Main Menu"),
+                            .title(if search.is_some() {
+                                "Search"
+                            } else {
+                                "Main Menu"
+                            }),
                     )
                     .style(style)
                     .highlight_style(style.fg(Color::Magenta).add_modifier(Modifier::BOLD)),
@@ -881,31 +1061,39 @@ impl Term {
             );
 
             let text;
+            let copy_label = if search.is_some() {
+                "Enter - Copy      "
+            } else {
+                "c - Copy      "
+            };
 
-            if alternate_footer.is_empty() {
-                let copy_text;
-                match copy_status {
-                    Status::None => {
-                        copy_text = Span::raw("c - Copy      ");
-                    }
-                    Status::Success => {
-                        copy_text =
-                            Span::styled("c - Copy      ", Style::default().fg(Color::Green));
-                    }
-                    Status::Fail => {
-                        copy_text = Span::styled("c - Copy      ", Style::default().fg(Color::Red));
-                    }
-                }
-
+            if !alternate_footer.is_empty() {
+                text = Spans::from(vec![Span::raw(alternate_footer)]);
+            } else if let Some(query) = search {
                 text = Spans::from(vec![
+                    Span::raw("/"),
+                    Span::styled(query, Style::default().add_modifier(Modifier::BOLD)),
+                    Span::raw("_      "),
+                    Term::copy_span(copy_label, &copy_status),
+                    Span::raw("Esc - Close search"),
+                ]);
+            } else {
+                let copy_text = Term::copy_span(copy_label, &copy_status);
+                let mut spans = vec![
                     Span::raw("n - New      "),
                     Span::raw("e - Edit      "),
                     copy_text,
+                    Span::raw("g - Next HOTP      "),
                     Span::raw("r - Delete      "),
-                    Span::raw("q - Quit"),
-                ]);
-            } else {
-                text = Spans::from(vec![Span::raw(alternate_footer)]);
+                ];
+
+                if grouped {
+                    spans.push(Span::raw("Enter - Fold group      "));
+                }
+
+                spans.push(Span::raw("/ - Search      "));
+                spans.push(Span::raw("q - Quit"));
+                text = Spans::from(spans);
             }
 
             f.render_widget(
@@ -922,41 +1110,115 @@ impl Term {
         return Ok(());
     }
 
-    fn copy(&mut self) {
+    /// The footer entry for copying, coloured by whether the last copy succeeded.
+    fn copy_span(text: &'static str, status: &Status) -> Span<'static> {
+        match status {
+            Status::None => return Span::raw(text),
+            Status::Success => return Span::styled(text, Style::default().fg(Color::Green)),
+            Status::Fail => return Span::styled(text, Style::default().fg(Color::Red)),
+        }
+    }
+
+    /// Draws the part of the period that remains as a bar of fixed width.
+    fn countdown_bar(remaining: u64, period: u64) -> String {
+        let filled = (remaining * COUNTDOWN_WIDTH as u64)
+            .checked_div(period)
+            .unwrap_or(0) as usize;
+
+        return format!(
+            "{}{}",
+            "█".repeat(filled.min(COUNTDOWN_WIDTH)),
+            "░".repeat(COUNTDOWN_WIDTH - filled.min(COUNTDOWN_WIDTH))
+        );
+    }
+
+    fn copy(&mut self) -> Result<(), &'static str> {
         let code;
 
-        if self.current_menu == TermMenu::Main {
-            match self.items[self.selected_index].get_code() {
-                Ok(c) => code = c,
-                Err(_) => {
-                    self.copy_status = Status::Fail;
-                    return;
+        if let Some(index) = self.selected_item() {
+            if self.items[index].kind == Kind::Hotp {
+                if !self.hotp_codes.contains_key(&self.items[index].label) {
+                    self.generate()?;
+                }
+
+                match self.hotp_codes.get(&self.items[index].label) {
+                    Some(c) => code = c.clone(),
+                    None => {
+                        self.copy_status = Status::Fail;
+                        return Ok(());
+                    }
+                }
+            } else {
+                match self
+                    .clock
+                    .now()
+                    .and_then(|time| self.items[index].get_code_at(time))
+                {
+                    Ok(c) => code = c,
+                    Err(_) => {
+                        self.copy_status = Status::Fail;
+                        return Ok(());
+                    }
                 }
             }
         } else {
-            return;
+            return Ok(());
         }
 
-        if let Ok(mut clipboard) = Clipboard::new() {
-            match clipboard.set_text(code) {
-                Ok(_) => self.copy_status = Status::Success,
-                Err(_) => self.copy_status = Status::Fail,
+        match CopiedCode::copy(&code, self.clear_after) {
+            Ok(copied) => {
+                self.copied = Some(copied);
+                self.copy_status = Status::Success;
             }
-        } else {
-            self.copy_status = Status::Fail;
+            Err(_) => self.copy_status = Status::Fail,
         }
+
+        return Ok(());
+    }
+
+    /// Generates the next code of the selected HOTP item and saves the advanced counter.
+    fn generate(&mut self) -> Result<(), &'static str> {
+        let item;
+        match self.selected_item() {
+            Some(index) => item = &mut self.items[index],
+            None => return Ok(()),
+        }
+
+        if item.kind != Kind::Hotp {
+            return Ok(());
+        }
+
+        // The time doesn't affect HOTP codes.
+        match item.generate_code(0) {
+            Ok(code) => {
+                self.hotp_codes.insert(item.label.clone(), code);
+            }
+            Err(_) => {
+                self.alternate_footer = String::from("Could not generate the next code.");
+                return Ok(());
+            }
+        }
+
+        self.save();
+
+        return Ok(());
     }
 
     fn remove(&mut self) -> Result<(), &'static str> {
-        if self.current_menu == TermMenu::Main && self.selected_index < self.items.len() {
-            self.items.remove(self.selected_index);
+        if let Some(index) = self.selected_item() {
+            self.items.remove(index);
 
             if self.selected_index > 0 {
                 self.selected_index -= 1;
             }
 
-            self.save()?;
-            self.reset_changing_fields();
+            self.check_selected_index();
+
+            if self.save() {
+                self.reset_changing_fields();
+            } else {
+                self.pending_confirmation = false;
+            }
         }
 
         return Ok(());
@@ -969,24 +1231,254 @@ impl Term {
     }
 
     fn quit(&mut self) {
-        let _ = self.save();
+        // After a failed save the user has been warned, so quitting again discards the changes.
+        if !self.save() && !self.save_failed_before_quit {
+            self.save_failed_before_quit = true;
+            self.alternate_footer
+                .push_str(" Press q again to quit without saving.");
+            return;
+        }
+
+        self.clear_copied();
+
         let _ = self.terminal.show_cursor();
         let _ = self.terminal.clear();
 
         std::process::exit(0);
     }
 
-    fn save(&self) -> Result<(), &'static str> {
-        match item_storage::write_items(&item_storage::storage_location(), &self.items) {
-            Ok(()) => return Ok(()),
+    /// Clears a copied code from the clipboard before exiting, rather than leaving it there once
+    /// nothing is left to clear it after its timeout.
+    fn clear_copied(&mut self) {
+        if let Some(mut copied) = self.copied.take() {
+            copied.clear();
+        }
+    }
+
+    /// Saves the items, returning false and showing the error in the footer if that fails. The
+    /// database on disk is left as it was in that case.
+    fn save(&mut self) -> bool {
+        match self.save_merged() {
+            Ok(()) => return true,
             Err(e) => {
-                eprintln!("An error occurred when saving the database: {}", e);
-                std::process::exit(1);
+                self.alternate_footer = format!("Could not save the database: {}", e);
+                return false;
+            }
+        }
+    }
+
+    /// Saves the items, first merging in any changes made to the database by another otpc process
+    /// since it was loaded so that they aren't overwritten.
+    fn save_merged(&mut self) -> Result<(), String> {
+        if self.items == self.base_items {
+            return Ok(());
+        }
+
+        let path = item_storage::storage_location();
+        let _lock = item_storage::lock_database(&path)?;
+
+        if item_storage::fingerprint(&path) != self.fingerprint {
+            let current = item_storage::retrieve_items(&path, &self.passphrase)?;
+            self.items = item_storage::merge_items(&self.base_items, &self.items, &current);
+            self.check_selected_index();
+        }
+
+        item_storage::write_items(&path, &self.items, &self.passphrase)?;
+        self.base_items = self.items.clone();
+        self.fingerprint = item_storage::fingerprint(&path);
+
+        return Ok(());
+    }
+
+    /// Picks up changes made to the database by another otpc process, such as items added from the
+    /// command line while this session is open.
+    fn reload_if_changed(&mut self) {
+        let path = item_storage::storage_location();
+
+        if item_storage::fingerprint(&path) == self.fingerprint {
+            return;
+        }
+
+        let _lock;
+        match item_storage::lock_database(&path) {
+            Ok(l) => _lock = l,
+            Err(e) => {
+                self.alternate_footer = e;
+                return;
+            }
+        }
+
+        // Remember the new version even if it can't be read, so that it isn't retried every second.
+        self.fingerprint = item_storage::fingerprint(&path);
+
+        match item_storage::retrieve_items(&path, &self.passphrase) {
+            Ok(current) => {
+                self.items = item_storage::merge_items(&self.base_items, &self.items, &current);
+                self.base_items = current;
+                self.check_selected_index();
+                self.alternate_footer =
+                    String::from("The database was changed by another otpc process and reloaded.");
+            }
+            Err(e) => {
+                self.alternate_footer = format!("Could not reload the database: {}", e);
+            }
+        }
+    }
+
+    /// Keeps the selection of the main menu within its rows after items were removed.
+    fn check_selected_index(&mut self) {
+        let row_count = self.main_rows().len();
+
+        if self.current_menu == TermMenu::Main && self.selected_index >= row_count {
+            self.selected_index = row_count.saturating_sub(1);
+        }
+    }
+
+    /// The lines of the main menu. The items are listed as they are until one of them is tagged,
+    /// then they are grouped under each of their tags, followed by the untagged items.
+    fn main_rows(&self) -> Vec<MainRow> {
+        // Search results are listed best match first, without groups.
+        if self.search.is_some() {
+            let mut matches: Vec<(i64, usize)> = (0..self.items.len())
+                .filter_map(|index| {
+                    self.search_match(&self.items[index])
+                        .map(|(score, _)| (score, index))
+                })
+                .collect();
+
+            matches.sort_by_key(|&(score, index)| (std::cmp::Reverse(score), index));
+
+            return matches
+                .into_iter()
+                .map(|(_, index)| MainRow::Item(index))
+                .collect();
+        }
+
+        if self.items.iter().all(|item| item.tags.is_empty()) {
+            return (0..self.items.len()).map(MainRow::Item).collect();
+        }
+
+        let mut groups: Vec<Option<String>> = Vec::new();
+
+        for tag in self.items.iter().flat_map(|item| item.tags.iter()) {
+            if !groups.contains(&Term::group_key(&Some(tag.clone()))) {
+                groups.push(Term::group_key(&Some(tag.clone())));
+            }
+        }
+
+        groups.sort();
+
+        if self.items.iter().any(|item| item.tags.is_empty()) {
+            groups.push(None);
+        }
+
+        let mut rows = Vec::new();
+
+        for group in groups {
+            // Show the tag as it was first written rather than in lowercase.
+            let name = group.as_ref().and_then(|key| {
+                self.items
+                    .iter()
+                    .flat_map(|item| item.tags.iter())
+                    .find(|tag| &tag.to_lowercase() == key)
+                    .cloned()
+            });
+
+            rows.push(MainRow::Group(name.clone()));
+
+            if !self.collapsed_groups.contains(&group) {
+                for index in 0..self.items.len() {
+                    if Term::in_group(&self.items[index], &name) {
+                        rows.push(MainRow::Item(index));
+                    }
+                }
+            }
+        }
+
+        return rows;
+    }
+
+    /// The text shown for an item in the main menu, its label followed by the issuer and account.
+    fn item_name(item: &Item) -> String {
+        let description = item.description();
+
+        if description.is_empty() {
+            return item.label.clone();
+        }
+
+        return format!("{}  {}", item.label, description);
+    }
+
+    /// Matches the search query against the name of the item, then its tags. The positions of
+    /// the matched characters are only returned for a match in the name, as only it is shown.
+    fn search_match(&self, item: &Item) -> Option<(i64, Vec<usize>)> {
+        let query = self.search.as_deref().unwrap_or("");
+
+        match fuzzy_match(query, &Term::item_name(item)) {
+            Some(m) => return Some(m),
+            None => {
+                return fuzzy_match(query, &item.tags.join(" "))
+                    .map(|(score, _)| (score, Vec::new()))
+            }
+        }
+    }
+
+    /// Splits the text into spans, with the characters at the positions highlighted.
+    fn highlighted(text: &str, positions: &Vec<usize>) -> Vec<Span<'static>> {
+        let mut spans = Vec::new();
+
+        for (index, c) in text.chars().enumerate() {
+            if positions.contains(&index) {
+                spans.push(Span::styled(
+                    c.to_string(),
+                    Style::default()
+                        .fg(Color::Yellow)
+                        .add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+                ));
+            } else {
+                spans.push(Span::raw(c.to_string()));
+            }
+        }
+
+        return spans;
+    }
+
+    fn group_key(tag: &Option<String>) -> Option<String> {
+        return tag.as_ref().map(|t| t.to_lowercase());
+    }
+
+    fn in_group(item: &Item, tag: &Option<String>) -> bool {
+        match tag {
+            Some(tag) => return item.has_tag(tag),
+            None => return item.tags.is_empty(),
+        }
+    }
+
+    /// The index of the item on the selected line of the main menu, if it isn't a group heading.
+    fn selected_item(&self) -> Option<usize> {
+        if self.current_menu != TermMenu::Main {
+            return None;
+        }
+
+        match self.main_rows().get(self.selected_index) {
+            Some(MainRow::Item(index)) => return Some(*index),
+            _ => return None,
+        }
+    }
+
+    /// Folds or unfolds the group whose heading is selected.
+    fn toggle_group(&mut self) {
+        if let Some(MainRow::Group(tag)) = self.main_rows().get(self.selected_index) {
+            let key = Term::group_key(tag);
+
+            if !self.collapsed_groups.remove(&key) {
+                self.collapsed_groups.insert(key);
             }
         }
     }
 
     fn get_key(
+        &mut self,
         receiver: &Receiver<Result<termion::event::Key, std::io::Error>>,
     ) -> Result<Option<termion::event::Key>, &'static str> {
         // We use a timeout because otherwise the loop runs too fast and consumes alot of the CPU,
@@ -995,10 +1487,9 @@ impl Term {
             Ok(c) => match c {
                 Ok(k) => {
                     match k {
-                        Key::Ctrl(c) => {
-                            if c == 'c' {
-                                std::process::exit(2);
-                            }
+                        Key::Ctrl('c') => {
+                            self.clear_copied();
+                            std::process::exit(2);
                         }
                         _ => (),
                     }
@@ -1027,3 +1518,59 @@ impl Term {
         return rx;
     }
 }
+
+/// The byte index of the character at a cursor column of a field, whose characters may be
+/// several bytes long or several cells wide.
+fn column_to_index(str: &str, column: u16) -> usize {
+    let mut width = 0;
+
+    for (index, c) in str.char_indices() {
+        if width >= column as usize {
+            return index;
+        }
+
+        width += c.width().unwrap_or(0);
+    }
+
+    return str.len();
+}
+
+/// Inserts a character at the cursor column, returning the column after it.
+fn insert_at_column(str: &mut String, column: u16, c: char) -> u16 {
+    str.insert(column_to_index(str, column), c);
+
+    return str[..column_to_index(str, column)].width() as u16 + c.width().unwrap_or(0) as u16;
+}
+
+/// Removes the character before the cursor column, returning the column it started at.
+fn remove_before_column(str: &mut String, column: u16) -> u16 {
+    let index = column_to_index(str, column);
+
+    match str[..index].char_indices().next_back() {
+        Some((start, _)) => {
+            str.remove(start);
+            return str[..start].width() as u16;
+        }
+        None => return column.min(str.width() as u16),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    pub fn test_edit_multibyte() {
+        use super::*;
+        let mut issuer = String::from("Soct");
+
+        assert_eq!(insert_at_column(&mut issuer, 3, 'i'), 4);
+        assert_eq!(insert_at_column(&mut issuer, 4, 'é'), 5);
+        assert_eq!(insert_at_column(&mut issuer, 6, 'é'), 7);
+        assert_eq!(issuer, "Société");
+        assert_eq!(insert_at_column(&mut issuer, 7, '字'), 9);
+        assert_eq!(remove_before_column(&mut issuer, 9), 7);
+        assert_eq!(remove_before_column(&mut issuer, 7), 6);
+        assert_eq!(remove_before_column(&mut issuer, 5), 4);
+        assert_eq!(issuer, "Socit");
+        assert_eq!(remove_before_column(&mut issuer, 0), 0);
+    }
+}
@@ -1,19 +1,98 @@
-use lotp::totp;
+use crate::otp;
+use crate::util::normalize_secret;
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
-pub enum Digits {
-    Six,
-    Seven,
-    Eight,
+/// The shortest and longest codes that can be generated.
+pub const MIN_DIGITS: u32 = 4;
+pub const MAX_DIGITS: u32 = 10;
+
+/// Parses the number of digits in a code, as entered by the user or found in an import.
+pub fn parse_digits(text: &str) -> Result<u32, String> {
+    match text.trim().parse::<u32>() {
+        Ok(digits) if (MIN_DIGITS..=MAX_DIGITS).contains(&digits) => return Ok(digits),
+        _ => return Err(digits_error()),
+    }
+}
+
+fn digits_error() -> String {
+    return format!(
+        "The number of digits must be between {} and {}.",
+        MIN_DIGITS, MAX_DIGITS
+    );
+}
+
+/// The HMAC hash function used to generate codes.
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug, Default)]
+pub enum Algorithm {
+    #[default]
+    Sha1,
+    Sha256,
+    Sha512,
 }
 
-impl std::fmt::Display for Digits {
+impl std::fmt::Display for Algorithm {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Digits::Six => return write!(f, "6"),
-            Digits::Seven => return write!(f, "7"),
-            Digits::Eight => return write!(f, "8"),
+            Algorithm::Sha1 => return write!(f, "SHA1"),
+            Algorithm::Sha256 => return write!(f, "SHA256"),
+            Algorithm::Sha512 => return write!(f, "SHA512"),
+        }
+    }
+}
+
+impl std::str::FromStr for Algorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('-', "").as_str() {
+            "sha1" => return Ok(Algorithm::Sha1),
+            "sha256" => return Ok(Algorithm::Sha256),
+            "sha512" => return Ok(Algorithm::Sha512),
+            _ => {
+                return Err(String::from(
+                    "The algorithm must be one of sha1, sha256 or sha512.",
+                ))
+            }
+        }
+    }
+}
+
+/// Whether an item is time based (TOTP) or counter based (HOTP). Steam Guard items are time based,
+/// but their codes are written with Steam's own alphabet.
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug, Default)]
+pub enum Kind {
+    #[default]
+    Totp,
+    Hotp,
+    Steam,
+}
+
+impl std::fmt::Display for Kind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Kind::Totp => return write!(f, "totp"),
+            Kind::Hotp => return write!(f, "hotp"),
+            Kind::Steam => return write!(f, "steam"),
+        }
+    }
+}
+
+impl Kind {
+    /// Returns true if the codes change with the time rather than a counter.
+    pub fn is_time_based(&self) -> bool {
+        return *self != Kind::Hotp;
+    }
+}
+
+impl std::str::FromStr for Kind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "totp" => return Ok(Kind::Totp),
+            "hotp" => return Ok(Kind::Hotp),
+            "steam" => return Ok(Kind::Steam),
+            _ => return Err(String::from("The type must be totp, hotp or steam.")),
         }
     }
 }
@@ -22,41 +101,332 @@ impl std::fmt::Display for Digits {
 pub struct Item {
     pub label: String,
     pub secret: String,
-    pub digits: Digits,
+    pub digits: u32,
     pub split_time: u32,
+    #[serde(default)]
+    pub kind: Kind,
+    /// The counter of the next HOTP code, unused by TOTP items.
+    #[serde(default)]
+    pub counter: u64,
+    #[serde(default)]
+    pub algorithm: Algorithm,
+    #[serde(default)]
+    pub issuer: Option<String>,
+    /// The name of the account at the issuer, such as a username or email address.
+    #[serde(default)]
+    pub account: Option<String>,
+    /// Tags for grouping and filtering items, e.g. "work".
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Seconds added to the time when generating TOTP codes, for issuers whose clock is off.
+    #[serde(default)]
+    pub time_offset: i64,
+    /// The unix time that TOTP periods are counted from, 0 for nearly every issuer.
+    #[serde(default)]
+    pub t0: u64,
+}
+
+impl Default for Item {
+    /// A TOTP item with the usual settings and no label or secret.
+    fn default() -> Self {
+        return Item {
+            label: String::new(),
+            secret: String::new(),
+            digits: 6,
+            split_time: 30,
+            kind: Kind::Totp,
+            counter: 0,
+            algorithm: Algorithm::Sha1,
+            issuer: None,
+            account: None,
+            tags: Vec::new(),
+            time_offset: 0,
+            t0: 0,
+        };
+    }
 }
 
 impl std::fmt::Display for Item {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         return write!(
             f,
-            "({}, {}, {}, {})",
-            self.label, self.secret, self.digits, self.split_time
+            "({}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {})",
+            self.label,
+            self.secret,
+            self.digits,
+            self.split_time,
+            self.kind,
+            self.counter,
+            self.algorithm,
+            self.issuer.clone().unwrap_or_default(),
+            self.account.clone().unwrap_or_default(),
+            self.tags.join(","),
+            self.time_offset,
+            self.t0
         );
     }
 }
 
 impl Item {
-    pub fn get_code(&self) -> Result<String, String> {
-        match &self.digits {
-            Digits::Six => {
-                match totp::generate_6_digit_totp_string(&self.secret, &(self.split_time as u64)) {
-                    Ok(s) => return Ok(s),
-                    Err(e) => return Err(e.description()),
-                }
+    /// Checks the fields that every item must satisfy, whether it was entered by hand or imported.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.label.trim().is_empty() {
+            return Err(String::from("A label is required."));
+        }
+
+        if self.label.trim() != self.label {
+            return Err(String::from(
+                "The label cannot start or end with whitespace.",
+            ));
+        }
+
+        if self.label.chars().any(|c| c.is_control()) {
+            return Err(String::from(
+                "The label cannot contain tabs, newlines or other control characters.",
+            ));
+        }
+
+        if !(MIN_DIGITS..=MAX_DIGITS).contains(&self.digits) {
+            return Err(digits_error());
+        }
+
+        if self.kind == Kind::Steam && self.digits != otp::STEAM_CODE_LENGTH {
+            return Err(format!(
+                "Steam Guard codes always have {} characters.",
+                otp::STEAM_CODE_LENGTH
+            ));
+        }
+
+        for tag in self.tags.iter() {
+            if tag.trim().is_empty() || tag.trim() != tag {
+                return Err(String::from(
+                    "Tags cannot be empty or start or end with whitespace.",
+                ));
+            }
+
+            if tag.contains(',') || tag.chars().any(|c| c.is_control()) {
+                return Err(String::from(
+                    "Tags cannot contain commas or control characters.",
+                ));
+            }
+        }
+
+        if normalize_secret(&self.secret).as_ref() != Ok(&self.secret) {
+            return Err(String::from("A valid base-32 secret is required."));
+        }
+
+        if self.split_time == 0 {
+            return Err(String::from("A valid period greater than 0 is required."));
+        }
+
+        return Ok(());
+    }
+
+    /// Describes the issuer and account of the item, e.g. "ACME Co (alice)", or an empty string if
+    /// neither is set.
+    pub fn description(&self) -> String {
+        match (&self.issuer, &self.account) {
+            (Some(issuer), Some(account)) => return format!("{} ({})", issuer, account),
+            (Some(issuer), None) => return issuer.clone(),
+            (None, Some(account)) => return account.clone(),
+            (None, None) => return String::new(),
+        }
+    }
+
+    /// Returns true if the item has the tag, ignoring case.
+    pub fn has_tag(&self, tag: &str) -> bool {
+        return self
+            .tags
+            .iter()
+            .any(|t| t.to_lowercase() == tag.trim().to_lowercase());
+    }
+
+    /// Returns the code at a unix timestamp, which only affects time based items, or the HOTP code
+    /// for the current counter without advancing it.
+    pub fn get_code_at(&self, time: u64) -> Result<String, String> {
+        if self.kind == Kind::Hotp {
+            return otp::hotp(&self.secret, self.counter, self.digits, &self.algorithm);
+        }
+
+        let since_t0;
+        match self.offset_time(time).checked_sub(self.t0) {
+            Some(t) => since_t0 = t,
+            None => return Err(String::from("The time is before the T0 of the item.")),
+        }
+
+        match &self.kind {
+            Kind::Steam => {
+                return otp::steam_at(
+                    &self.secret,
+                    self.split_time as u64,
+                    since_t0,
+                    &self.algorithm,
+                )
+            }
+            _ => {
+                return otp::totp_at(
+                    &self.secret,
+                    self.split_time as u64,
+                    since_t0,
+                    self.digits,
+                    &self.algorithm,
+                )
+            }
+        }
+    }
+
+    /// Returns the code that follows the one at the timestamp, from the next period of a time
+    /// based item or the next counter of a HOTP item.
+    pub fn next_code_at(&self, time: u64) -> Result<String, String> {
+        match &self.kind {
+            Kind::Hotp => {
+                return otp::hotp(&self.secret, self.counter + 1, self.digits, &self.algorithm)
             }
-            Digits::Seven => {
-                match totp::generate_7_digit_totp_string(&self.secret, &(self.split_time as u64)) {
-                    Ok(s) => return Ok(s),
-                    Err(e) => return Err(e.description()),
+            _ => return self.get_code_at(time + self.split_time as u64),
+        }
+    }
+
+    /// Returns how many seconds the code of a time based item remains valid for at the timestamp.
+    pub fn remaining_seconds_at(&self, time: u64) -> Option<u64> {
+        if !self.kind.is_time_based() || self.split_time == 0 {
+            return None;
+        }
+
+        let since_t0 = self.offset_time(time).saturating_sub(self.t0);
+        return Some(self.split_time as u64 - since_t0 % self.split_time as u64);
+    }
+
+    /// Checks a code against the codes within window time steps of the timestamp, or the window
+    /// counters following the stored one for HOTP items. The offset of the matching step is
+    /// returned, the closest being preferred, or None if the code doesn't match.
+    pub fn verify_code_at(
+        &self,
+        code: &str,
+        time: u64,
+        window: u64,
+    ) -> Result<Option<i64>, String> {
+        match &self.kind {
+            Kind::Totp | Kind::Steam => {
+                let period = self.split_time as u64;
+
+                for distance in 0..=window {
+                    // Steps beyond the range of a timestamp can't match.
+                    let shift;
+                    match distance.checked_mul(period) {
+                        Some(s) => shift = s,
+                        None => break,
+                    }
+
+                    for offset in [-(distance as i64), distance as i64] {
+                        let step_time;
+                        match if offset < 0 {
+                            time.checked_sub(shift)
+                        } else {
+                            time.checked_add(shift)
+                        } {
+                            Some(t) => step_time = t,
+                            None => continue,
+                        }
+
+                        // There are no codes before T0.
+                        if self.offset_time(step_time) < self.t0 {
+                            continue;
+                        }
+
+                        // Steam Guard codes are typed in either case.
+                        if self.get_code_at(step_time)?.eq_ignore_ascii_case(code) {
+                            return Ok(Some(offset));
+                        }
+                    }
                 }
             }
-            Digits::Eight => {
-                match totp::generate_8_digit_totp_string(&self.secret, &(self.split_time as u64)) {
-                    Ok(s) => return Ok(s),
-                    Err(e) => return Err(e.description()),
+            Kind::Hotp => {
+                for offset in 0..=window {
+                    let counter;
+                    match self.counter.checked_add(offset) {
+                        Some(c) => counter = c,
+                        None => break,
+                    }
+
+                    let expected = otp::hotp(&self.secret, counter, self.digits, &self.algorithm)?;
+
+                    if expected == code {
+                        return Ok(Some(offset as i64));
+                    }
                 }
             }
         }
+
+        return Ok(None);
+    }
+
+    /// Applies the time offset of the item to a timestamp.
+    fn offset_time(&self, time: u64) -> u64 {
+        return time.saturating_add_signed(self.time_offset);
+    }
+
+    /// Returns the code at the timestamp, advancing the counter of HOTP items so that each code is
+    /// only generated once. The item must be saved afterwards to persist the new counter.
+    pub fn generate_code(&mut self, time: u64) -> Result<String, String> {
+        let code = self.get_code_at(time)?;
+
+        if self.kind == Kind::Hotp {
+            self.counter += 1;
+        }
+
+        return Ok(code);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    pub fn test_parse_digits() {
+        use super::*;
+        assert_eq!(parse_digits("4"), Ok(4));
+        assert_eq!(parse_digits(" 10 "), Ok(10));
+        assert!(parse_digits("3").is_err());
+        assert!(parse_digits("11").is_err());
+        assert!(parse_digits("six").is_err());
+    }
+
+    #[test]
+    pub fn test_verify_code_at() {
+        use super::*;
+        let mut item = Item {
+            label: String::from("test"),
+            secret: String::from("GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ"),
+            digits: 8,
+            ..Default::default()
+        };
+
+        // The RFC 6238 test vector for time 59 is in the step two periods before 119.
+        assert_eq!(item.verify_code_at("94287082", 59, 0), Ok(Some(0)));
+        assert_eq!(item.verify_code_at("94287082", 119, 1), Ok(None));
+        assert_eq!(item.verify_code_at("94287082", 119, 2), Ok(Some(-2)));
+        assert_eq!(item.verify_code_at("94287082", 0, 1), Ok(Some(1)));
+        assert_eq!(item.verify_code_at("12345678", 59, 3), Ok(None));
+
+        // Moving T0 forward by a period moves the codes with it, and there are none before T0.
+        item.t0 = 30;
+        assert_eq!(item.verify_code_at("94287082", 89, 0), Ok(Some(0)));
+        assert_eq!(item.verify_code_at("94287082", 59, 1), Ok(Some(1)));
+        assert!(item.get_code_at(29).is_err());
+        item.t0 = 0;
+
+        // The RFC 4226 test vectors are for 6 digits.
+        item.kind = Kind::Hotp;
+        item.digits = 6;
+        item.counter = 2;
+        assert_eq!(item.verify_code_at("969429", 0, 0), Ok(None));
+        assert_eq!(item.verify_code_at("969429", 0, 1), Ok(Some(1)));
+        assert_eq!(item.verify_code_at("338314", 0, 2), Ok(Some(2)));
+        assert_eq!(item.verify_code_at("755224", 0, 5), Ok(None));
+
+        // Steps and counters past the end of their range are left out rather than overflowing.
+        item.counter = u64::MAX - 1;
+        assert_eq!(item.verify_code_at("755224", 0, 5), Ok(None));
+        item.kind = Kind::Totp;
+        assert_eq!(item.verify_code_at("12345678", u64::MAX - 10, 5), Ok(None));
     }
 }
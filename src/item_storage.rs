@@ -1,62 +1,549 @@
-use crate::item::Item;
-use std::fs::OpenOptions;
-use std::io::{BufReader, BufWriter, Write};
-use std::path::Path;
+use crate::item::{Item, Kind};
+use crate::vault;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const DATABASE_VARIABLE: &'static str = "OTPC_DB";
+
+// How long to wait for another otpc process to finish with the database.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+// The version of the database layout written by this version of otpc. Older databases are
+// upgraded by the migrations, MIGRATIONS[n] taking a database from version n to n + 1.
+const SCHEMA_VERSION: u32 = 2;
+const MIGRATIONS: [fn(Value) -> Result<Value, String>; SCHEMA_VERSION as usize] =
+    [migrate_bare_array, migrate_digit_names];
+
+static STORAGE_LOCATION: OnceLock<String> = OnceLock::new();
+
+/// The layout of the database inside the vault.
+#[derive(Serialize, Deserialize)]
+struct Database {
+    schema_version: u32,
+    /// The version of otpc that last saved the database.
+    written_by: String,
+    items: Vec<Item>,
+}
+
+/// An exclusive lock on the database, shared between otpc processes. The lock is released when
+/// this is dropped.
+pub struct DatabaseLock {
+    _file: File,
+}
 
 pub fn storage_location_exists() -> bool {
     return Path::new(&storage_location()).exists();
 }
 
+/// The path of the database used by this process, see resolve_storage_location.
 pub fn storage_location() -> String {
-    match dirs::home_dir() {
+    match STORAGE_LOCATION.get() {
+        Some(path) => return path.clone(),
+        None => match resolve_storage_location(&None, &None) {
+            Ok(path) => return path,
+            Err(_) => return String::new(),
+        },
+    }
+}
+
+/// Sets the database used for the rest of this process.
+pub fn set_storage_location(path: String) {
+    let _ = STORAGE_LOCATION.set(path);
+}
+
+/// Finds the database to use: the path given, otherwise the named vault in the data directory,
+/// otherwise the path in OTPC_DB, otherwise the default vault in the data directory.
+pub fn resolve_storage_location(
+    path: &Option<String>,
+    vault: &Option<String>,
+) -> Result<String, String> {
+    if let Some(path) = path {
+        return Ok(path.clone());
+    }
+
+    let file_name;
+    match vault {
+        Some(name) => {
+            if name.is_empty()
+                || !name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                return Err(String::from(
+                    "Vault names may only contain letters, numbers, '-' and '_'.",
+                ));
+            }
+
+            file_name = format!("{}.json", name);
+        }
+        None => {
+            match std::env::var(DATABASE_VARIABLE) {
+                Ok(path) if !path.is_empty() => return Ok(path),
+                _ => (),
+            }
+
+            file_name = String::from("items.json");
+        }
+    }
+
+    let mut path = data_directory()?;
+    path.push(file_name);
+
+    match path.to_str() {
+        Some(p) => return Ok(String::from(p)),
+        None => return Err(String::from("The data directory is not valid unicode.")),
+    }
+}
+
+/// The directory holding the vaults. This is ~/.otpc when it exists, from before the XDG data
+/// directory was used, and otherwise otpc in the data directory, e.g. ~/.local/share/otpc.
+pub fn data_directory() -> Result<PathBuf, String> {
+    if let Some(mut legacy) = dirs::home_dir() {
+        legacy.push(".otpc");
+
+        if legacy.is_dir() {
+            return Ok(legacy);
+        }
+    }
+
+    match dirs::data_dir() {
         Some(mut path) => {
-            path.push(".otpc");
-            path.push("items.json");
-            return String::from(path.to_str().unwrap());
+            path.push("otpc");
+            return Ok(path);
         }
-        None => return String::new(),
+        None => return Err(String::from("Could not determine the data directory.")),
+    }
+}
+
+/// Returns true if the database at the path exists and is an encrypted vault.
+pub fn is_encrypted(path: &String) -> bool {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => return vault::is_vault(&contents),
+        Err(_) => return false,
     }
 }
 
-pub fn write_items(path: &String, items: &Vec<Item>) -> Result<(), String> {
-    if Path::new(path).exists() {
-        match std::fs::remove_file(path) {
+/// Waits for and takes the lock on the database at the path. The lock is held on a separate
+/// file, as saving replaces the database file itself.
+pub fn lock_database(path: &String) -> Result<DatabaseLock, String> {
+    let file;
+    match OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(format!("{}.lock", path))
+    {
+        Ok(f) => file = f,
+        Err(e) => return Err(format!("Could not open the database lock file: {}", e)),
+    }
+
+    let start = Instant::now();
+
+    loop {
+        match file.try_lock() {
+            Ok(()) => return Ok(DatabaseLock { _file: file }),
+            Err(TryLockError::WouldBlock) => {
+                if start.elapsed() > LOCK_TIMEOUT {
+                    return Err(String::from(
+                        "The database is in use by another otpc process.",
+                    ));
+                }
+
+                thread::sleep(Duration::from_millis(100));
+            }
+            Err(TryLockError::Error(e)) => {
+                return Err(format!("Could not lock the database: {}", e))
+            }
+        }
+    }
+}
+
+/// Returns a hash of the database file, which changes whenever the database is saved, or None
+/// if it can't be read.
+#[cfg_attr(not(feature = "interactive"), allow(dead_code))]
+pub fn fingerprint(path: &String) -> Option<Vec<u8>> {
+    match fs::read(path) {
+        Ok(contents) => return Some(Sha256::digest(contents).to_vec()),
+        Err(_) => return None,
+    }
+}
+
+/// Combines the changes made to base in local with those made to it in another process, as found
+/// in current. Items are matched by label, and local changes win when both changed an item, except
+/// that the higher HOTP counter is kept so that no code is issued twice.
+#[cfg_attr(not(feature = "interactive"), allow(dead_code))]
+pub fn merge_items(base: &Vec<Item>, local: &Vec<Item>, current: &Vec<Item>) -> Vec<Item> {
+    let mut merged = current.clone();
+
+    // Items removed or renamed locally.
+    for item in base {
+        if !local.iter().any(|i| i.label == item.label) {
+            merged.retain(|i| i.label != item.label);
+        }
+    }
+
+    // Items added or changed locally.
+    for item in local {
+        if base.contains(item) {
+            continue;
+        }
+
+        match merged.iter_mut().find(|i| i.label == item.label) {
+            Some(existing) => {
+                let changed_in_both = !base.contains(existing);
+                let counter = existing.counter;
+                *existing = item.clone();
+
+                if changed_in_both && existing.kind == Kind::Hotp {
+                    existing.counter = existing.counter.max(counter);
+                }
+            }
+            None => merged.push(item.clone()),
+        }
+    }
+
+    return merged;
+}
+
+/// Saves the items, encrypted with the passphrase, through replace_file so that the old database
+/// is left intact if writing fails. The previous version is kept alongside as a .bak file, unless
+/// it was a plaintext database.
+pub fn write_items(path: &String, items: &Vec<Item>, passphrase: &String) -> Result<(), String> {
+    let sealed = vault::seal(encode_items(items)?.as_bytes(), passphrase)?;
+    let backup_path = format!("{}.bak", path);
+
+    match fs::read_to_string(path) {
+        Ok(contents) if vault::is_vault(&contents) => match fs::copy(path, &backup_path) {
             Ok(_) => (),
-            Err(_) => return Err(String::from("Could not remove the old file.")),
-        }
-    }
-
-    match OpenOptions::new().create(true).write(true).open(path) {
-        Ok(file) => {
-            let mut writer = BufWriter::new(file);
-            match serde_json::to_string(items) {
-                Ok(s) => match writer.write_all(s.as_bytes()) {
-                    Ok(_) => {
-                        let _ = writer.flush();
-                        return Ok(());
-                    }
-                    Err(e) => return Err(e.to_string()),
-                },
-                Err(e) => return Err(e.to_string()),
+            Err(e) => return Err(format!("Could not back up the database: {}", e)),
+        },
+        // A plaintext database being encrypted is not kept, nor any backup made of it before.
+        Ok(_) => match fs::remove_file(&backup_path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => {
+                return Err(format!("Could not remove the plaintext backup: {}", e))
             }
+            _ => (),
+        },
+        Err(_) => (),
+    }
+
+    return replace_file(path, sealed.as_bytes(), "database");
+}
+
+/// Writes the contents to a temporary file in the same directory and then renames it over the
+/// file at the path, so that a crash never leaves a partly written file behind. What names the
+/// file in error messages.
+pub fn replace_file(path: &String, contents: &[u8], what: &str) -> Result<(), String> {
+    let temp_path = format!("{}.tmp", path);
+
+    match write_synced(&temp_path, contents, path) {
+        Ok(_) => (),
+        Err(e) => {
+            let _ = fs::remove_file(&temp_path);
+            return Err(format!("Could not write the {}: {}", what, e));
+        }
+    }
+
+    match fs::rename(&temp_path, path) {
+        Ok(_) => (),
+        Err(e) => {
+            let _ = fs::remove_file(&temp_path);
+            return Err(format!("Could not replace the {}: {}", what, e));
+        }
+    }
+
+    // The rename itself is only durable once the directory has been synced.
+    #[cfg(unix)]
+    if let Some(directory) = Path::new(path).parent() {
+        let directory = if directory.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            directory
+        };
+
+        match File::open(directory).and_then(|d| d.sync_all()) {
+            Ok(_) => (),
+            Err(e) => return Err(format!("Could not sync the {} directory: {}", what, e)),
         }
-        Err(e) => return Err(e.to_string()),
+    }
+
+    return Ok(());
+}
+
+/// Writes the contents to a new file at the path and waits for them to reach the disk. The file
+/// gets the permissions of the file at original_path if there is one.
+fn write_synced(path: &String, contents: &[u8], original_path: &String) -> std::io::Result<()> {
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(path)?;
+
+    if let Ok(metadata) = fs::metadata(original_path) {
+        file.set_permissions(metadata.permissions())?;
+    }
+
+    let mut writer = BufWriter::new(file);
+    writer.write_all(contents)?;
+    writer.flush()?;
+
+    match writer.into_inner() {
+        Ok(file) => return file.sync_all(),
+        Err(e) => return Err(e.into_error()),
     }
 }
 
-pub fn retrieve_items(path: &String) -> Result<Vec<Item>, String> {
+/// Reads the items from the database, decrypting it with the passphrase. A legacy plaintext
+/// database is read as is and then rewritten as a vault encrypted with the passphrase.
+pub fn retrieve_items(path: &String, passphrase: &String) -> Result<Vec<Item>, String> {
     if !Path::new(path).exists() {
         return Err(String::from("File does not exist"));
     }
 
-    match OpenOptions::new().create(false).read(true).open(path) {
-        Ok(file) => {
-            let reader = BufReader::new(file);
-            match serde_json::from_reader(reader) {
-                Ok(items) => return Ok(items),
-                Err(e) => return Err(e.to_string()),
-            }
-        }
+    let contents;
+    match std::fs::read_to_string(path) {
+        Ok(s) => contents = s,
         Err(e) => return Err(e.to_string()),
     }
+
+    if vault::is_vault(&contents) {
+        let plaintext = vault::open(&contents, passphrase)?;
+        return decode_items(&plaintext);
+    }
+
+    let items = decode_items(contents.as_bytes())?;
+
+    write_items(path, &items, passphrase)?;
+
+    return Ok(items);
+}
+
+/// Serializes the items in the current database schema.
+fn encode_items(items: &Vec<Item>) -> Result<String, String> {
+    let database = Database {
+        schema_version: SCHEMA_VERSION,
+        written_by: format!("otpc {}", env!("CARGO_PKG_VERSION")),
+        items: items.clone(),
+    };
+
+    match serde_json::to_string(&database) {
+        Ok(s) => return Ok(s),
+        Err(e) => return Err(e.to_string()),
+    }
+}
+
+/// Reads the items from a serialized database of any schema version up to the current one.
+fn decode_items(contents: &[u8]) -> Result<Vec<Item>, String> {
+    let value;
+    match serde_json::from_slice(contents) {
+        Ok(v) => value = upgrade(v)?,
+        Err(e) => return Err(format!("The database is malformed: {}", e)),
+    }
+
+    match serde_json::from_value::<Database>(value) {
+        Ok(database) => return Ok(database.items),
+        Err(e) => return Err(format!("The database is malformed: {}", e)),
+    }
+}
+
+/// Brings a database of an older schema up to the current one, applying each migration in turn.
+fn upgrade(mut value: Value) -> Result<Value, String> {
+    let mut version;
+    match &value {
+        // The first databases were a bare array of items.
+        Value::Array(_) => version = 0,
+        Value::Object(fields) => match fields.get("schema_version").and_then(|v| v.as_u64()) {
+            Some(v) => version = v,
+            None => return Err(String::from("The database has no schema version.")),
+        },
+        _ => return Err(String::from("The database is malformed.")),
+    }
+
+    if version > SCHEMA_VERSION as u64 {
+        return Err(format!(
+            "The database was written by a newer version of otpc (schema version {}), please \
+             upgrade otpc to open it.",
+            version
+        ));
+    }
+
+    while version < SCHEMA_VERSION as u64 {
+        value = MIGRATIONS[version as usize](value)?;
+        version += 1;
+    }
+
+    return Ok(value);
+}
+
+/// Version 0 to 1: wraps the bare array of items in the versioned envelope.
+fn migrate_bare_array(value: Value) -> Result<Value, String> {
+    return Ok(json!({
+        "schema_version": 1,
+        "written_by": "",
+        "items": value,
+    }));
+}
+
+/// Version 1 to 2: the number of digits was stored as the name "Six", "Seven" or "Eight".
+fn migrate_digit_names(mut value: Value) -> Result<Value, String> {
+    if let Some(items) = value["items"].as_array_mut() {
+        for item in items.iter_mut() {
+            let digits = match item["digits"].as_str() {
+                Some("Six") => 6,
+                Some("Seven") => 7,
+                Some("Eight") => 8,
+                Some(name) => return Err(format!("Unknown number of digits '{}'.", name)),
+                None => continue,
+            };
+
+            item["digits"] = json!(digits);
+        }
+    }
+
+    value["schema_version"] = json!(2);
+    return Ok(value);
+}
+
+#[cfg(test)]
+mod test {
+    use crate::item::Item;
+
+    fn item(label: &str) -> Item {
+        return Item {
+            label: String::from(label),
+            secret: String::from("jbswy3dpehpk3pxp"),
+            ..Default::default()
+        };
+    }
+
+    #[test]
+    pub fn test_write_items() {
+        use super::*;
+        let directory = std::env::temp_dir().join(format!("otpc-test-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let path = String::from(directory.join("items.json").to_str().unwrap());
+        let passphrase = String::from("passphrase");
+
+        write_items(&path, &vec![item("first")], &passphrase).unwrap();
+        assert!(!Path::new(&format!("{}.bak", path)).exists());
+        let first = fs::read_to_string(&path).unwrap();
+
+        write_items(&path, &vec![item("second")], &passphrase).unwrap();
+        assert_eq!(fs::read_to_string(format!("{}.bak", path)).unwrap(), first);
+        assert_ne!(fs::read_to_string(&path).unwrap(), first);
+        assert!(!Path::new(&format!("{}.tmp", path)).exists());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    pub fn test_retrieve_items_plaintext() {
+        use super::*;
+        let directory =
+            std::env::temp_dir().join(format!("otpc-plaintext-test-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let path = String::from(directory.join("items.json").to_str().unwrap());
+        let backup_path = format!("{}.bak", path);
+        let plaintext = serde_json::to_string(&vec![item("first")]).unwrap();
+        fs::write(&path, &plaintext).unwrap();
+        fs::write(&backup_path, &plaintext).unwrap();
+
+        let items = retrieve_items(&path, &String::from("passphrase")).unwrap();
+
+        assert_eq!(items, vec![item("first")]);
+        assert!(vault::is_vault(&fs::read_to_string(&path).unwrap()));
+        assert!(!Path::new(&backup_path).exists());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    pub fn test_resolve_storage_location() {
+        use super::*;
+        let path = Some(String::from("/tmp/items.json"));
+
+        assert_eq!(
+            resolve_storage_location(&path, &None).unwrap(),
+            "/tmp/items.json"
+        );
+        assert!(resolve_storage_location(&None, &Some(String::from("work")))
+            .unwrap()
+            .ends_with("work.json"));
+        assert!(resolve_storage_location(&None, &Some(String::from("../work"))).is_err());
+        assert!(resolve_storage_location(&None, &Some(String::new())).is_err());
+    }
+
+    #[test]
+    pub fn test_decode_items() {
+        use super::*;
+        let items = vec![item("a"), item("b")];
+        let encoded = encode_items(&items).unwrap();
+
+        assert!(encoded.starts_with("{\"schema_version\":2,"));
+        assert_eq!(decode_items(encoded.as_bytes()).unwrap(), items);
+
+        // Databases from before the schema was versioned.
+        let legacy = serde_json::to_string(&items).unwrap();
+        assert_eq!(decode_items(legacy.as_bytes()).unwrap(), items);
+
+        // Version 1 databases named the number of digits.
+        let named = r#"{"schema_version": 1, "written_by": "otpc 2.0.0", "items": [
+            {"label": "a", "secret": "test", "digits": "Eight", "split_time": 30}]}"#;
+        assert_eq!(decode_items(named.as_bytes()).unwrap()[0].digits, 8);
+    }
+
+    #[test]
+    pub fn test_decode_items_newer() {
+        use super::*;
+        let newer = r#"{"schema_version": 100, "written_by": "otpc 99.0.0", "items": []}"#;
+
+        assert!(decode_items(newer.as_bytes())
+            .unwrap_err()
+            .contains("newer version"));
+        assert!(decode_items(b"{\"items\": []}").is_err());
+    }
+
+    #[test]
+    pub fn test_merge_items() {
+        use super::*;
+        let base = vec![item("a"), item("b"), item("c")];
+        let mut edited = item("b");
+        edited.counter = 5;
+
+        // Locally b was edited and c removed, another process removed a and added d.
+        let local = vec![item("a"), edited.clone(), item("e")];
+        let current = vec![item("b"), item("c"), item("d")];
+        let merged = merge_items(&base, &local, &current);
+
+        assert_eq!(merged, vec![edited, item("d"), item("e")]);
+    }
+
+    #[test]
+    pub fn test_merge_items_hotp() {
+        use super::*;
+        let mut base = item("bank");
+        base.kind = Kind::Hotp;
+        base.counter = 10;
+        let mut local = base.clone();
+        local.counter = 11;
+        local.digits = 8;
+        let mut current = base.clone();
+        current.counter = 12;
+
+        let merged = merge_items(&vec![base.clone()], &vec![local.clone()], &vec![current]);
+        local.counter = 12;
+        assert_eq!(merged, vec![local.clone()]);
+
+        // A counter set back locally is kept when the other process didn't use the item.
+        local.counter = 3;
+        let merged = merge_items(&vec![base.clone()], &vec![local.clone()], &vec![base]);
+        assert_eq!(merged, vec![local]);
+    }
 }
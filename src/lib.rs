@@ -1,6 +1,18 @@
+mod aegis;
+mod andotp;
+#[cfg(feature = "clipboard")]
+mod clipboard;
 #[cfg(feature = "interactive")]
 mod interactive;
 mod item;
 mod item_storage;
+mod migration;
 pub mod modes;
+mod otp;
+mod output;
+#[cfg(feature = "qr")]
+mod qr;
+mod settings;
+mod uri;
 mod util;
+mod vault;
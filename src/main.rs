@@ -1,39 +1,291 @@
-use clap::Parser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use otpc::modes::{ItemOptions, OutputFormat};
+
+/// How long a copied code stays on the clipboard by default, in seconds.
+#[cfg(feature = "clipboard")]
+const DEFAULT_CLEAR_AFTER: u64 = 30;
+// Each step of the window is another code to generate, and a larger window would accept most
+// codes anyway.
+const MAX_VERIFY_WINDOW: u64 = 100;
 
 #[derive(Parser)]
 #[command(author, version, about = "A Command Line One-Time Password client.", long_about = None, arg_required_else_help(true))]
 struct Cli {
-    #[arg(long, short = 'n', help = "Add a new item", conflicts_with_all = ["list", "remove", "code", "interactive"])]
-    new: bool,
     #[arg(
         long,
-        short = 'l',
-        help = "List the stored items and their current code", conflicts_with_all = ["new", "remove", "code", "interactive"]
+        global = true,
+        value_name = "PATH",
+        help = "The database file to use, also read from OTPC_DB"
     )]
-    list: bool,
+    db: Option<String>,
     #[arg(
         long,
-        short = 'r',
-        value_name = "LABEL",
-        help = "Remove the specified item", conflicts_with_all = ["list", "new", "code", "interactive"]
+        global = true,
+        value_name = "NAME",
+        conflicts_with = "db",
+        help = "Use a separate named vault in the data directory"
     )]
-    remove: Option<String>,
+    vault: Option<String>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(
+        about = "Add a new item, prompting for its fields unless a label, URI or image is given"
+    )]
+    Add {
+        #[arg(long, help = "The label of the new item")]
+        label: Option<String>,
+        #[arg(long, value_name = "URI", help = "Add the item from an otpauth:// URI", conflicts_with_all = ["label", "qr"])]
+        uri: Option<String>,
+        #[cfg(feature = "qr")]
+        #[arg(long, value_name = "PATH", help = "Add the item from a PNG or JPEG image of a QR code", conflicts_with_all = ["label", "uri"])]
+        qr: Option<String>,
+        #[command(flatten)]
+        fields: ItemArgs,
+    },
+    #[command(about = "List the stored items and their current code")]
+    List {
+        #[arg(long, value_enum, default_value = "text")]
+        format: Format,
+        #[arg(long, help = "Only list the items with this tag")]
+        tag: Option<String>,
+        #[arg(
+            long,
+            value_name = "SECONDS",
+            help = "Also show the next code of items with no more than this many seconds left"
+        )]
+        next_within: Option<u64>,
+        #[arg(
+            long,
+            value_name = "TIME",
+            help = "Generate the codes for a unix timestamp or RFC 3339 time instead of now"
+        )]
+        at: Option<String>,
+    },
+    #[command(about = "Get the current code of an item")]
+    Code {
+        label: String,
+        #[arg(
+            long,
+            help = "Only accept the exact label, rather than a prefix or fuzzy match"
+        )]
+        exact: bool,
+        #[arg(
+            long,
+            value_name = "TIME",
+            help = "Generate the code for a unix timestamp or RFC 3339 time instead of now"
+        )]
+        at: Option<String>,
+        #[cfg(feature = "clipboard")]
+        #[arg(
+            long,
+            help = "Copy the code to the clipboard instead of printing it",
+            conflicts_with_all = ["format", "at"]
+        )]
+        copy: bool,
+        #[cfg(feature = "clipboard")]
+        #[arg(
+            long,
+            value_name = "SECONDS",
+            default_value_t = DEFAULT_CLEAR_AFTER,
+            help = "Clear a copied code from the clipboard after this many seconds, 0 to keep it"
+        )]
+        clear_after: u64,
+        #[arg(long, value_enum, default_value = "text")]
+        format: Format,
+    },
+    #[command(about = "Check whether a code is valid for an item, allowing for clock drift")]
+    Verify {
+        label: String,
+        code: String,
+        #[arg(
+            long,
+            value_name = "N",
+            default_value_t = 1,
+            value_parser = clap::value_parser!(u64).range(..=MAX_VERIFY_WINDOW),
+            help = "Also accept the codes up to N time steps, or HOTP counters, away"
+        )]
+        window: u64,
+        #[arg(
+            long,
+            value_name = "TIME",
+            help = "Check against a unix timestamp or RFC 3339 time rather than now"
+        )]
+        at: Option<String>,
+    },
+    #[command(about = "Show or change the settings shared by every vault")]
+    Settings {
+        #[arg(
+            long,
+            value_name = "SECONDS",
+            allow_negative_numbers = true,
+            help = "Seconds to add to the system clock when generating codes, to correct a skewed clock"
+        )]
+        time_offset: Option<i64>,
+    },
+    #[command(about = "Remove an item")]
+    Remove { label: String },
+    #[command(about = "Change the settings of an item")]
+    Edit {
+        label: String,
+        #[command(flatten)]
+        fields: ItemArgs,
+    },
+    #[command(about = "Change the label of an item")]
+    Rename { label: String, new_label: String },
+    #[command(about = "Show the settings of an item")]
+    Show {
+        label: String,
+        #[arg(
+            long,
+            help = "Print the otpauth:// URI of the item, including its secret"
+        )]
+        uri: bool,
+    },
+    #[command(about = "Import items from another authenticator")]
+    Import {
+        format: ImportFormat,
+        #[arg(
+            required = true,
+            help = "The vault file for aegis, the plain backup for andotp, the otpauth-migration:// URIs or QR code images for google"
+        )]
+        sources: Vec<String>,
+    },
+    #[command(about = "Export every item, to stdout unless an output file is given")]
+    Export {
+        format: ExportFormat,
+        #[arg(
+            long,
+            short = 'o',
+            value_name = "PATH",
+            help = "Write the export to a file"
+        )]
+        output: Option<String>,
+        #[arg(long, help = "Encrypt the exported Aegis vault with a password")]
+        encrypt: bool,
+    },
+    #[cfg(feature = "interactive")]
+    #[command(about = "Enter interactive mode")]
+    Interactive {
+        #[arg(
+            long,
+            value_name = "SECONDS",
+            default_value_t = 5,
+            help = "Show the next code of items with no more than this many seconds left"
+        )]
+        next_within: u64,
+        #[arg(
+            long,
+            value_name = "SECONDS",
+            default_value_t = DEFAULT_CLEAR_AFTER,
+            help = "Clear a copied code from the clipboard after this many seconds, 0 to keep it"
+        )]
+        clear_after: u64,
+    },
+}
+
+#[derive(Args)]
+struct ItemArgs {
+    #[arg(long, help = "The base-32 secret")]
+    secret: Option<String>,
     #[arg(
         long,
-        short = 'c',
-        value_name = "LABEL",
-        help = "Get the current code of an item", conflicts_with_all = ["list", "remove", "new", "interactive"]
+        value_name = "PATH",
+        help = "Read the secret from a file, or from stdin if the path is -",
+        conflicts_with = "secret"
     )]
-    code: Option<String>,
-    #[cfg(feature = "interactive")]
-    #[arg(long, short = 'i', help = "Enter interactive mode", conflicts_with_all = ["list", "remove", "code", "new"])]
-    interactive: bool,
+    secret_file: Option<String>,
+    #[arg(long, help = "The service that issued the item")]
+    issuer: Option<String>,
+    #[arg(long, help = "The account name at the issuer, such as a username")]
+    account: Option<String>,
+    #[arg(
+        long,
+        value_name = "TAGS",
+        help = "Comma separated tags for grouping items, replacing any existing tags"
+    )]
+    tags: Option<String>,
+    #[arg(long = "type", value_name = "TYPE", help = "totp, hotp or steam")]
+    kind: Option<String>,
+    #[arg(long, help = "sha1, sha256 or sha512")]
+    algorithm: Option<String>,
+    #[arg(long, help = "The number of digits in a code, from 4 to 10")]
+    digits: Option<u32>,
+    #[arg(long, help = "The token period of a TOTP item in seconds")]
+    period: Option<u32>,
+    #[arg(long, help = "The counter of a HOTP item")]
+    counter: Option<u64>,
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        allow_negative_numbers = true,
+        help = "Seconds to add to the time for this item, for issuers whose clock is off"
+    )]
+    time_offset: Option<i64>,
+    #[arg(
+        long,
+        value_name = "UNIX_TIME",
+        help = "The time TOTP periods are counted from, for services that don't use 0"
+    )]
+    t0: Option<u64>,
+}
+
+impl ItemArgs {
+    fn options(self) -> ItemOptions {
+        return ItemOptions {
+            secret: self.secret,
+            secret_file: self.secret_file,
+            issuer: self.issuer,
+            account: self.account,
+            tags: self.tags,
+            kind: self.kind,
+            algorithm: self.algorithm,
+            digits: self.digits,
+            period: self.period,
+            counter: self.counter,
+            time_offset: self.time_offset,
+            t0: self.t0,
+        };
+    }
+}
+
+#[derive(Clone, ValueEnum)]
+enum Format {
+    Text,
+    Json,
+    Tsv,
+}
+
+impl Format {
+    fn output_format(self) -> OutputFormat {
+        match self {
+            Format::Text => return OutputFormat::Text,
+            Format::Json => return OutputFormat::Json,
+            Format::Tsv => return OutputFormat::Tsv,
+        }
+    }
+}
+
+#[derive(Clone, ValueEnum)]
+enum ImportFormat {
+    Aegis,
+    Andotp,
+    Google,
+}
+
+#[derive(Clone, ValueEnum)]
+enum ExportFormat {
+    Aegis,
+    Uri,
 }
 
 fn main() {
     let cli = Cli::parse();
 
-    match otpc::modes::run_startup_checks() {
+    match otpc::modes::run_startup_checks(&cli.db, &cli.vault) {
         Some(s) => {
             println!("{}", s);
             std::process::exit(1);
@@ -41,26 +293,100 @@ fn main() {
         None => (),
     }
 
-    if cli.new {
-        otpc::modes::run_new();
-        return;
-    } else if cli.list {
-        otpc::modes::run_list();
-        return;
-    } else if let Some(label) = cli.remove {
-        otpc::modes::run_remove(&String::from(label));
+    match cli.command {
+        Command::Add {
+            label,
+            uri,
+            #[cfg(feature = "qr")]
+            qr,
+            fields,
+        } => {
+            #[cfg(feature = "qr")]
+            if let Some(path) = qr {
+                otpc::modes::run_add_qr(&path);
+                return;
+            }
 
-        return;
-    } else if let Some(label) = cli.code {
-        otpc::modes::run_display_code(&String::from(label));
+            let options = fields.options();
 
-        return;
-    }
+            if let Some(uri) = uri {
+                otpc::modes::run_add_uri(&uri);
+            } else if let Some(label) = label {
+                otpc::modes::run_add(&label, &options);
+            } else if options.is_empty() {
+                otpc::modes::run_new();
+            } else {
+                eprintln!("A label is required, given with --label.");
+                std::process::exit(1);
+            }
+        }
+        Command::List {
+            format,
+            tag,
+            next_within,
+            at,
+        } => otpc::modes::run_list(format.output_format(), &tag, next_within, &at),
+        Command::Code {
+            label,
+            exact,
+            at,
+            #[cfg(feature = "clipboard")]
+            copy,
+            #[cfg(feature = "clipboard")]
+            clear_after,
+            format,
+        } => {
+            #[cfg(feature = "clipboard")]
+            if copy {
+                otpc::modes::run_copy_code(&label, exact, clear_after);
+                return;
+            }
 
-    #[cfg(feature = "interactive")]
-    if cli.interactive {
-        otpc::modes::run_interactive();
+            otpc::modes::run_display_code(&label, exact, &at, format.output_format())
+        }
+        Command::Verify {
+            label,
+            code,
+            window,
+            at,
+        } => otpc::modes::run_verify(&label, &code, window, &at),
+        Command::Settings { time_offset } => otpc::modes::run_settings(time_offset),
+        Command::Remove { label } => otpc::modes::run_remove(&label),
+        Command::Edit { label, fields } => otpc::modes::run_edit(&label, &fields.options()),
+        Command::Rename { label, new_label } => otpc::modes::run_rename(&label, &new_label),
+        Command::Show { label, uri } => otpc::modes::run_show(&label, uri),
+        Command::Import { format, sources } => match format {
+            ImportFormat::Aegis => {
+                for path in sources.iter() {
+                    otpc::modes::run_import_aegis(path);
+                }
+            }
+            ImportFormat::Andotp => {
+                for path in sources.iter() {
+                    otpc::modes::run_import_andotp(path);
+                }
+            }
+            ImportFormat::Google => otpc::modes::run_import_migration(&sources),
+        },
+        Command::Export {
+            format,
+            output,
+            encrypt,
+        } => match format {
+            ExportFormat::Aegis => otpc::modes::run_export_aegis(&output, encrypt),
+            ExportFormat::Uri => {
+                if encrypt {
+                    eprintln!("Only Aegis exports can be encrypted.");
+                    std::process::exit(1);
+                }
 
-        return;
+                otpc::modes::run_export_uris(&output);
+            }
+        },
+        #[cfg(feature = "interactive")]
+        Command::Interactive {
+            next_within,
+            clear_after,
+        } => otpc::modes::run_interactive(next_within, clear_after),
     }
 }
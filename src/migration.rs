@@ -0,0 +1,354 @@
+use crate::item::{Algorithm, Item, Kind};
+use crate::util::import_label;
+use base64::engine::general_purpose::{GeneralPurpose, GeneralPurposeConfig};
+use base64::engine::DecodePaddingMode;
+use base64::{alphabet, Engine};
+use url::Url;
+
+const MIGRATION_SCHEME: &'static str = "otpauth-migration";
+
+// Exports are seen both with and without padding.
+const BASE64: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// One part of a Google Authenticator export. Large exports are split across several QR codes,
+/// each carrying the same batch id.
+#[derive(Debug)]
+pub struct Batch {
+    pub items: Vec<Item>,
+    /// Labels of the accounts that can't be represented, with the reason.
+    pub skipped: Vec<(String, String)>,
+    pub batch_size: u64,
+    pub batch_index: u64,
+    pub batch_id: u64,
+}
+
+/// Decodes an otpauth-migration://offline?data=... URI.
+pub fn parse_migration_uri(uri: &str) -> Result<Batch, String> {
+    let url;
+    match Url::parse(uri.trim()) {
+        Ok(u) => url = u,
+        Err(e) => return Err(format!("The URI is malformed: {}", e)),
+    }
+
+    if url.scheme() != MIGRATION_SCHEME {
+        return Err(String::from("The URI must start with otpauth-migration://"));
+    }
+
+    let data;
+    match url.query_pairs().find(|(key, _)| key == "data") {
+        // An unescaped '+' in the query is decoded as a space.
+        Some((_, value)) => data = value.replace(' ', "+"),
+        None => return Err(String::from("The URI does not contain any data.")),
+    }
+
+    let payload;
+    match BASE64.decode(data.as_bytes()) {
+        Ok(p) => payload = p,
+        Err(_) => return Err(String::from("The URI data is not valid base-64.")),
+    }
+
+    return parse_payload(&payload);
+}
+
+fn parse_payload(payload: &[u8]) -> Result<Batch, String> {
+    let mut batch = Batch {
+        items: Vec::new(),
+        skipped: Vec::new(),
+        batch_size: 1,
+        batch_index: 0,
+        batch_id: 0,
+    };
+    let mut reader = Reader {
+        data: payload,
+        position: 0,
+    };
+
+    while let Some((field, value)) = reader.field()? {
+        match (field, value) {
+            (1, Value::Bytes(parameters)) => match parse_parameters(parameters)? {
+                Ok(item) => batch.items.push(item),
+                Err(skipped) => batch.skipped.push(skipped),
+            },
+            (3, Value::Varint(v)) => batch.batch_size = v,
+            (4, Value::Varint(v)) => batch.batch_index = v,
+            (5, Value::Varint(v)) => batch.batch_id = v,
+            _ => (),
+        }
+    }
+
+    return Ok(batch);
+}
+
+/// Converts the parameters of a single account, returning the label and reason in the inner
+/// error when the account is of a kind that otpc can't generate codes for.
+fn parse_parameters(data: &[u8]) -> Result<Result<Item, (String, String)>, String> {
+    let mut secret = Vec::new();
+    let mut name = String::new();
+    let mut issuer = String::new();
+    let mut algorithm = 1;
+    let mut digits = 1;
+    let mut kind = 2;
+    let mut counter = 0;
+    let mut reader = Reader { data, position: 0 };
+
+    while let Some((field, value)) = reader.field()? {
+        match (field, value) {
+            (1, Value::Bytes(b)) => secret = b.to_vec(),
+            (2, Value::Bytes(b)) => name = String::from_utf8_lossy(b).trim().to_string(),
+            (3, Value::Bytes(b)) => issuer = String::from_utf8_lossy(b).trim().to_string(),
+            (4, Value::Varint(v)) => algorithm = v,
+            (5, Value::Varint(v)) => digits = v,
+            (6, Value::Varint(v)) => kind = v,
+            (7, Value::Varint(v)) => counter = v,
+            _ => (),
+        }
+    }
+
+    // The issuer is usually stored separately rather than as a prefix of the name.
+    let label = import_label(&issuer, &name);
+    let skip = |reason: &str| Ok(Err((label.clone(), String::from(reason))));
+
+    let algorithm = match algorithm {
+        0 | 1 => Algorithm::Sha1,
+        2 => Algorithm::Sha256,
+        3 => Algorithm::Sha512,
+        4 => return skip("the MD5 algorithm is not supported"),
+        _ => return skip("the algorithm is not recognised"),
+    };
+
+    let digits = match digits {
+        0 | 1 => 6,
+        2 => 8,
+        _ => return skip("the number of digits is not supported"),
+    };
+
+    let kind = match kind {
+        1 => Kind::Hotp,
+        2 => Kind::Totp,
+        _ => return skip("the type is not recognised"),
+    };
+
+    if secret.is_empty() {
+        return skip("the secret is empty");
+    }
+
+    return Ok(Ok(Item {
+        label,
+        secret: base32::encode(base32::Alphabet::RFC4648 { padding: false }, &secret)
+            .to_lowercase(),
+        digits,
+        split_time: 30,
+        kind,
+        counter,
+        algorithm,
+        issuer: if issuer.is_empty() {
+            None
+        } else {
+            Some(issuer)
+        },
+        account: if name.is_empty() { None } else { Some(name) },
+        tags: Vec::new(),
+        time_offset: 0,
+        t0: 0,
+    }));
+}
+
+enum Value<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Other,
+}
+
+// A minimal protocol buffers reader, enough for the migration payload.
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn varint(&mut self) -> Result<u64, String> {
+        let mut value = 0u64;
+
+        for shift in (0..64).step_by(7) {
+            let byte;
+            match self.data.get(self.position) {
+                Some(b) => byte = *b,
+                None => return Err(String::from("The migration data is truncated.")),
+            }
+
+            self.position += 1;
+            value |= ((byte & 0x7f) as u64) << shift;
+
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        return Err(String::from("The migration data is malformed."));
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], String> {
+        if length > self.data.len() - self.position {
+            return Err(String::from("The migration data is truncated."));
+        }
+
+        let bytes = &self.data[self.position..self.position + length];
+        self.position += length;
+
+        return Ok(bytes);
+    }
+
+    fn field(&mut self) -> Result<Option<(u64, Value<'a>)>, String> {
+        if self.position >= self.data.len() {
+            return Ok(None);
+        }
+
+        let key = self.varint()?;
+        let value = match key & 7 {
+            0 => Value::Varint(self.varint()?),
+            1 => {
+                self.bytes(8)?;
+                Value::Other
+            }
+            2 => {
+                let length = self.varint()? as usize;
+                Value::Bytes(self.bytes(length)?)
+            }
+            5 => {
+                self.bytes(4)?;
+                Value::Other
+            }
+            _ => return Err(String::from("The migration data is malformed.")),
+        };
+
+        return Ok(Some((key >> 3, value)));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use base64::Engine;
+
+    fn varint(mut value: u64, output: &mut Vec<u8>) {
+        while value >= 0x80 {
+            output.push((value as u8 & 0x7f) | 0x80);
+            value >>= 7;
+        }
+
+        output.push(value as u8);
+    }
+
+    fn bytes_field(field: u64, bytes: &[u8], output: &mut Vec<u8>) {
+        varint(field << 3 | 2, output);
+        varint(bytes.len() as u64, output);
+        output.extend_from_slice(bytes);
+    }
+
+    fn varint_field(field: u64, value: u64, output: &mut Vec<u8>) {
+        varint(field << 3, output);
+        varint(value, output);
+    }
+
+    fn parameters(name: &str, issuer: &str, algorithm: u64, kind: u64) -> Vec<u8> {
+        let mut output = Vec::new();
+        bytes_field(1, b"Hello!\xde\xad\xbe\xef", &mut output);
+        bytes_field(2, name.as_bytes(), &mut output);
+        bytes_field(3, issuer.as_bytes(), &mut output);
+        varint_field(4, algorithm, &mut output);
+        varint_field(5, 1, &mut output);
+        varint_field(6, kind, &mut output);
+
+        if kind == 1 {
+            varint_field(7, 300, &mut output);
+        }
+
+        return output;
+    }
+
+    fn migration_uri(accounts: &[Vec<u8>], size: u64, index: u64) -> String {
+        let mut payload = Vec::new();
+
+        for account in accounts {
+            bytes_field(1, account, &mut payload);
+        }
+
+        varint_field(2, 1, &mut payload);
+        varint_field(3, size, &mut payload);
+        varint_field(4, index, &mut payload);
+        varint_field(5, 123456789, &mut payload);
+
+        let data = base64::engine::general_purpose::STANDARD.encode(payload);
+
+        return format!(
+            "otpauth-migration://offline?data={}",
+            data.replace('+', "%2B")
+                .replace('/', "%2F")
+                .replace('=', "%3D")
+        );
+    }
+
+    #[test]
+    pub fn test_parse_migration_uri() {
+        use super::*;
+        let uri = migration_uri(
+            &[
+                parameters("alice@example.com", "Example", 1, 2),
+                parameters("bob", "", 2, 1),
+            ],
+            2,
+            1,
+        );
+        let batch = parse_migration_uri(&uri).unwrap();
+
+        assert_eq!(batch.items.len(), 2);
+        assert!(batch.skipped.is_empty());
+        assert_eq!(batch.batch_size, 2);
+        assert_eq!(batch.batch_index, 1);
+        assert_eq!(batch.batch_id, 123456789);
+
+        assert_eq!(batch.items[0].label, "Example:alice@example.com");
+        assert_eq!(batch.items[0].issuer, Some(String::from("Example")));
+        assert_eq!(batch.items[0].secret, "jbswy3dpehpk3pxp");
+        assert_eq!(batch.items[0].kind, Kind::Totp);
+        assert_eq!(batch.items[0].digits, 6);
+
+        assert_eq!(batch.items[1].label, "bob");
+        assert_eq!(batch.items[1].issuer, None);
+        assert_eq!(batch.items[1].algorithm, Algorithm::Sha256);
+        assert_eq!(batch.items[1].kind, Kind::Hotp);
+        assert_eq!(batch.items[1].counter, 300);
+    }
+
+    #[test]
+    pub fn test_parse_migration_uri_skipped() {
+        use super::*;
+        let uri = migration_uri(
+            &[
+                parameters("alice", "Example", 4, 2),
+                parameters("bob", "Example", 1, 0),
+                parameters("carol", "Example", 1, 2),
+            ],
+            1,
+            0,
+        );
+        let batch = parse_migration_uri(&uri).unwrap();
+
+        assert_eq!(batch.items.len(), 1);
+        assert_eq!(batch.items[0].label, "Example:carol");
+        assert_eq!(batch.skipped.len(), 2);
+        assert_eq!(batch.skipped[0].0, "Example:alice");
+        assert_eq!(batch.skipped[1].0, "Example:bob");
+    }
+
+    #[test]
+    pub fn test_parse_migration_uri_fail() {
+        use super::*;
+        assert!(parse_migration_uri("otpauth://totp/alice?secret=JBSWY3DPEHPK3PXP").is_err());
+        assert!(parse_migration_uri("otpauth-migration://offline").is_err());
+        assert!(parse_migration_uri("otpauth-migration://offline?data=!!!").is_err());
+        assert!(parse_migration_uri("otpauth-migration://offline?data=CgU").is_err());
+    }
+}
@@ -1,35 +1,278 @@
+use crate::aegis;
+use crate::andotp;
+#[cfg(feature = "clipboard")]
+use crate::clipboard::{CopiedCode, HOLD_WITHOUT_CLEAR};
+#[cfg(feature = "interactive")]
 use crate::interactive;
-use crate::item::{Digits, Item};
+use crate::item::{parse_digits, Algorithm, Item, Kind, MAX_DIGITS, MIN_DIGITS};
 use crate::item_storage;
 use crate::item_storage::{storage_location, storage_location_exists};
-use crate::util::{contains_item_label, is_base_32, is_number};
+use crate::migration;
+use crate::otp::{Clock, STEAM_CODE_LENGTH};
+use crate::output;
+use crate::output::CodeRecord;
+#[cfg(feature = "qr")]
+use crate::qr;
+use crate::settings;
+use crate::uri;
+use crate::util::{
+    contains_item_label, find_item, is_number, normalize_secret, parse_tags, parse_timestamp,
+    request_passphrase, request_secret,
+};
 use std::fs;
-use std::io::{stdin, stdout, Write};
+use std::io::{stdin, stdout, Read, Write};
+use std::path::Path;
+#[cfg(feature = "clipboard")]
+use std::time::{Duration, Instant};
+
+const AEGIS_PASSWORD_VARIABLE: &'static str = "OTPC_AEGIS_PASSWORD";
+
+/// How the list and code commands print their results.
+#[derive(Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Tsv,
+}
+
+/// Item fields given on the command line. Unset fields keep their defaults when adding an item,
+/// or their current values when editing one.
+#[derive(Default)]
+pub struct ItemOptions {
+    pub secret: Option<String>,
+    /// Read the secret from this file instead, or from stdin when it is "-".
+    pub secret_file: Option<String>,
+    pub issuer: Option<String>,
+    pub account: Option<String>,
+    /// A comma separated list that replaces the tags of the item.
+    pub tags: Option<String>,
+    pub kind: Option<String>,
+    pub algorithm: Option<String>,
+    pub digits: Option<u32>,
+    pub period: Option<u32>,
+    pub counter: Option<u64>,
+    pub time_offset: Option<i64>,
+    pub t0: Option<u64>,
+}
+
+impl ItemOptions {
+    pub fn is_empty(&self) -> bool {
+        return self.secret.is_none()
+            && self.secret_file.is_none()
+            && self.issuer.is_none()
+            && self.account.is_none()
+            && self.tags.is_none()
+            && self.kind.is_none()
+            && self.algorithm.is_none()
+            && self.digits.is_none()
+            && self.period.is_none()
+            && self.counter.is_none()
+            && self.time_offset.is_none()
+            && self.t0.is_none();
+    }
+
+    fn read_secret(&self) -> Result<Option<String>, String> {
+        if let Some(secret) = &self.secret {
+            return normalize_secret(secret).map(Some);
+        }
+
+        let path;
+        match &self.secret_file {
+            Some(p) => path = p,
+            None => return Ok(None),
+        }
+
+        let mut contents = String::new();
+
+        if path == "-" {
+            if stdin().read_to_string(&mut contents).is_err() {
+                return Err(String::from("Could not read the secret from stdin."));
+            }
+        } else {
+            match fs::read_to_string(path) {
+                Ok(c) => contents = c,
+                Err(e) => return Err(format!("Could not read '{}': {}", path, e)),
+            }
+        }
+
+        return normalize_secret(&contents).map(Some);
+    }
+
+    /// Overwrites the fields of the item that were given, then validates the result.
+    fn apply(&self, item: &mut Item) -> Result<(), String> {
+        if let Some(secret) = self.read_secret()? {
+            item.secret = secret;
+        }
+
+        if let Some(issuer) = &self.issuer {
+            item.issuer = if issuer.trim().is_empty() {
+                None
+            } else {
+                Some(String::from(issuer.trim()))
+            };
+        }
+
+        if let Some(account) = &self.account {
+            item.account = if account.trim().is_empty() {
+                None
+            } else {
+                Some(String::from(account.trim()))
+            };
+        }
+
+        if let Some(tags) = &self.tags {
+            item.tags = parse_tags(tags);
+        }
+
+        if let Some(kind) = &self.kind {
+            item.kind = kind.parse::<Kind>()?;
+        }
+
+        if let Some(algorithm) = &self.algorithm {
+            item.algorithm = algorithm.parse::<Algorithm>()?;
+        }
+
+        if let Some(digits) = self.digits {
+            item.digits = digits;
+        } else if item.kind == Kind::Steam {
+            item.digits = STEAM_CODE_LENGTH;
+        }
+
+        if let Some(period) = self.period {
+            item.split_time = period;
+        }
+
+        if let Some(counter) = self.counter {
+            item.counter = counter;
+        }
+
+        if let Some(time_offset) = self.time_offset {
+            item.time_offset = time_offset;
+        }
+
+        if let Some(t0) = self.t0 {
+            item.t0 = t0;
+        }
+
+        return item.validate();
+    }
+}
 
 #[cfg(feature = "interactive")]
-pub fn run_interactive() {
-    interactive::run();
+pub fn run_interactive(next_code_within: u64, clear_after: u64) {
+    interactive::run(next_code_within, clear_after, clock_or_exit(&None));
 }
 
-pub fn run_display_code(label: &String) {
-    if !storage_location_exists() {
-        println!("No database file found. Please add an item first.");
-        return;
+/// Prints the code of an item, at the time given as unix seconds or in the RFC 3339 format, or
+/// the current time.
+pub fn run_display_code(label: &String, exact: bool, at: &Option<String>, format: OutputFormat) {
+    let time = time_or_exit(&clock_or_exit(at));
+
+    let (item, code) = generate_item_code(label, exact, time, at.is_some());
+
+    match format {
+        OutputFormat::Text => println!("{} - {}", item.label, code),
+        OutputFormat::Json => {
+            println!("{}", output::to_json(&code_record(&item, time, Some(code))))
+        }
+        OutputFormat::Tsv => print!(
+            "{}",
+            output::to_tsv(&vec![code_record(&item, time, Some(code))])
+        ),
+    }
+}
+
+/// Copies the current code of an item to the clipboard, waiting to clear it again after
+/// clear_after seconds unless that is 0.
+#[cfg(feature = "clipboard")]
+pub fn run_copy_code(label: &String, exact: bool, clear_after: u64) {
+    let time = time_or_exit(&clock_or_exit(&None));
+
+    let (item, code) = generate_item_code(label, exact, time, false);
+
+    let mut copied;
+    match CopiedCode::copy(&code, clear_after) {
+        Ok(c) => copied = c,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+
+    // Some platforms only serve the clipboard while the process that set it is running, so otpc
+    // stays around until the code is cleared.
+    match copied.remaining() {
+        Some(remaining) => {
+            println!(
+                "Copied the code of '{}', it will be cleared in {} seconds.",
+                item.label,
+                remaining.as_secs()
+            );
+            std::thread::sleep(remaining);
+            copied.clear();
+        }
+        None => {
+            println!("Copied the code of '{}'.", item.label);
+
+            // The code isn't cleared, but it is still served for a while so that it can be pasted,
+            // unless something else is copied first.
+            let hold_until = Instant::now() + HOLD_WITHOUT_CLEAR;
+
+            while Instant::now() < hold_until && copied.is_held() {
+                std::thread::sleep(Duration::from_millis(250));
+            }
+        }
     }
+}
 
-    match item_storage::retrieve_items(&storage_location()) {
+/// Generates the code of the item a query refers to at the timestamp, saving the advanced counter
+/// of a HOTP item. Failures exit.
+fn generate_item_code(label: &String, exact: bool, time: u64, fixed_time: bool) -> (Item, String) {
+    require_database();
+
+    let passphrase = database_passphrase();
+    let _lock = lock_database();
+
+    match item_storage::retrieve_items(&storage_location(), &passphrase) {
         Ok(ref mut items) => {
-            for item in items {
-                if &item.label == label {
-                    match item.get_code() {
-                        Ok(code) => println!("{} - {}", item.label, code),
-                        Err(e) => {
-                            eprintln!("{}", e);
-                            std::process::exit(1);
-                        }
+            let item;
+            match find_item(label, items, exact) {
+                Ok(index) => item = &mut items[index],
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+
+            // Reproducing a past code must not use up the counter of a HOTP item.
+            if fixed_time && item.kind == Kind::Hotp {
+                eprintln!("HOTP codes don't depend on the time, --at only applies to TOTP items.");
+                std::process::exit(1);
+            }
+
+            let code;
+            match item.generate_code(time) {
+                Ok(c) => code = c,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+
+            let item = item.clone();
+
+            // HOTP codes may only be used once, so the advanced counter has to be persisted.
+            if item.kind == Kind::Hotp {
+                match item_storage::write_items(&storage_location(), items, &passphrase) {
+                    Ok(()) => (),
+                    Err(e) => {
+                        eprintln!("An error occurred when writing the database: {}", e);
+                        std::process::exit(1);
                     }
                 }
             }
+
+            return (item, code);
         }
         Err(e) => {
             eprintln!("An error occurred when reading the database: {}", e);
@@ -38,59 +281,378 @@ pub fn run_display_code(label: &String) {
     }
 }
 
-pub fn run_remove(label: &String) {
-    if !storage_location_exists() {
-        println!("No database file found. Please add an item first.");
-        return;
+/// Checks whether a code was valid for an item within window time steps of the given time, or the
+/// current time, and reports the step that matched. This doesn't use up HOTP codes.
+pub fn run_verify(label: &String, code: &String, window: u64, at: &Option<String>) {
+    require_database();
+
+    let time = time_or_exit(&clock_or_exit(at));
+
+    let passphrase = database_passphrase();
+    let _lock = lock_database();
+    let items = read_database(&passphrase);
+
+    let item;
+    match find_item(label, &items, false) {
+        Ok(index) => item = &items[index],
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
     }
 
-    match item_storage::retrieve_items(&storage_location()) {
-        Ok(ref mut items) => {
-            let mut index = 0;
+    let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+
+    match item.verify_code_at(&code, time, window) {
+        Ok(Some(offset)) => match item.kind {
+            Kind::Totp | Kind::Steam => {
+                let seconds = offset.unsigned_abs() * item.split_time as u64;
+                // A time within the step that matched, which verify_code_at keeps in range.
+                let matched_time = if offset < 0 {
+                    time - seconds
+                } else {
+                    time + seconds
+                };
 
-            while index < items.len() {
-                if &items[index].label == label {
-                    items.remove(index);
+                if offset == 0 {
+                    println!(
+                        "The code is valid for '{}' in the time step of unix time {}.",
+                        item.label, matched_time
+                    );
+                } else if offset < 0 {
+                    println!(
+                        "The code matches '{}' at unix time {}, {} time step(s) ({} seconds) earlier, the clock that generated it may be behind.",
+                        item.label, matched_time, -offset, seconds
+                    );
                 } else {
-                    index += 1;
+                    println!(
+                        "The code matches '{}' at unix time {}, {} time step(s) ({} seconds) later, the clock that generated it may be ahead.",
+                        item.label, matched_time, offset, seconds
+                    );
                 }
             }
+            Kind::Hotp => println!(
+                "The code matches '{}' at counter {}, {} ahead of the stored counter.",
+                item.label,
+                item.counter + offset as u64,
+                offset
+            ),
+        },
+        Ok(None) => {
+            match item.kind {
+                Kind::Totp | Kind::Steam => println!(
+                    "The code doesn't match '{}' within {} time step(s) of the time.",
+                    item.label, window
+                ),
+                Kind::Hotp => println!(
+                    "The code doesn't match '{}' within {} counter(s) of the stored counter {}.",
+                    item.label, window, item.counter
+                ),
+            }
+
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Changes the settings that apply to every vault, or prints them if none are given.
+pub fn run_settings(time_offset: Option<i64>) {
+    let path;
+    let mut current;
+    match settings::settings_location().and_then(|p| settings::read_settings(&p).map(|s| (p, s))) {
+        Ok((p, s)) => {
+            path = p;
+            current = s;
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
 
-            match item_storage::write_items(&storage_location(), items) {
-                Ok(()) => println!("Successfully removed '{}' from the database.", label),
+    match time_offset {
+        Some(offset) => {
+            current.time_offset = offset;
+
+            match settings::write_settings(&path, &current) {
+                Ok(()) => println!("The system clock is now offset by {} seconds.", offset),
                 Err(e) => {
-                    eprintln!("An error occurred when writing the database: {}", e);
+                    eprintln!("{}", e);
                     std::process::exit(1);
                 }
             }
         }
+        None => println!("Time offset: {} seconds", current.time_offset),
+    }
+}
+
+pub fn run_remove(label: &String) {
+    require_database();
+
+    let passphrase = database_passphrase();
+    let _lock = lock_database();
+
+    let mut items = read_database(&passphrase);
+
+    // Only the exact label is accepted, so that a mistyped label can't remove another item.
+    match find_item(label, &items, true) {
+        Ok(index) => {
+            items.remove(index);
+        }
         Err(e) => {
-            eprintln!("An error occurred when reading the database: {}", e);
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+
+    write_database(&items, &passphrase);
+    println!("Successfully removed '{}' from the database.", label);
+}
+
+/// Changes the given fields of an item, the counter of a HOTP item can be resynchronised this way.
+pub fn run_edit(label: &String, options: &ItemOptions) {
+    require_database();
+
+    if options.is_empty() {
+        eprintln!("No changes were given.");
+        std::process::exit(1);
+    }
+
+    let passphrase = database_passphrase();
+    let _lock = lock_database();
+    let mut items = read_database(&passphrase);
+
+    match items.iter_mut().find(|item| &item.label == label) {
+        Some(item) => match options.apply(item) {
+            Ok(()) => (),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        },
+        None => {
+            eprintln!("No item with the label '{}' exists.", label);
+            std::process::exit(1);
+        }
+    }
+
+    write_database(&items, &passphrase);
+    println!("Successfully updated '{}'.", label);
+}
+
+pub fn run_rename(label: &String, new_label: &String) {
+    require_database();
+
+    let passphrase = database_passphrase();
+    let _lock = lock_database();
+    let mut items = read_database(&passphrase);
+
+    if contains_item_label(new_label, &items) {
+        eprintln!("An item with the label '{}' already exists.", new_label);
+        std::process::exit(1);
+    }
+
+    match items.iter_mut().find(|item| &item.label == label) {
+        Some(item) => {
+            item.label = new_label.clone();
+
+            match item.validate() {
+                Ok(()) => (),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => {
+            eprintln!("No item with the label '{}' exists.", label);
+            std::process::exit(1);
+        }
+    }
+
+    write_database(&items, &passphrase);
+    println!("Renamed '{}' to '{}'.", label, new_label);
+}
+
+/// Prints the settings of an item, or its otpauth:// URI which includes the secret.
+pub fn run_show(label: &String, as_uri: bool) {
+    require_database();
+
+    let passphrase = database_passphrase();
+    let _lock = lock_database();
+    let items = read_database(&passphrase);
+
+    match items.iter().find(|item| &item.label == label) {
+        Some(item) => {
+            if as_uri {
+                println!("{}", uri::to_uri(item));
+                return;
+            }
+
+            println!("Label: {}", item.label);
+
+            if let Some(issuer) = &item.issuer {
+                println!("Issuer: {}", issuer);
+            }
+
+            if let Some(account) = &item.account {
+                println!("Account: {}", account);
+            }
+
+            if !item.tags.is_empty() {
+                println!("Tags: {}", item.tags.join(", "));
+            }
+
+            println!("Type: {}", item.kind);
+            println!("Algorithm: {}", item.algorithm);
+            println!("Digits: {}", item.digits);
+
+            match item.kind {
+                Kind::Totp | Kind::Steam => println!("Token period: {} seconds", item.split_time),
+                Kind::Hotp => println!("Counter: {}", item.counter),
+            }
+
+            if item.time_offset != 0 {
+                println!("Time offset: {} seconds", item.time_offset);
+            }
+
+            if item.t0 != 0 {
+                println!("T0: {}", item.t0);
+            }
+        }
+        None => {
+            eprintln!("No item with the label '{}' exists.", label);
             std::process::exit(1);
         }
     }
 }
 
-pub fn run_list() {
+/// Lists the items, or only those with the tag if one is given. In the text format the next code
+/// is shown for TOTP items that have no more than next_code_within seconds left.
+pub fn run_list(
+    format: OutputFormat,
+    tag: &Option<String>,
+    next_code_within: Option<u64>,
+    at: &Option<String>,
+) {
     if !storage_location_exists() {
-        println!("No database file found. Please add an item first.");
+        // Scripts get an empty list rather than a message they can't parse.
+        match format {
+            OutputFormat::Json => println!("{}", output::to_json(&Vec::<CodeRecord>::new())),
+            OutputFormat::Tsv => print!("{}", output::to_tsv(&Vec::new())),
+            OutputFormat::Text => println!("No database file found. Please add an item first."),
+        }
+
         return;
     }
 
-    match item_storage::retrieve_items(&storage_location()) {
+    let clock = clock_or_exit(at);
+
+    let passphrase = database_passphrase();
+    let _lock = lock_database();
+
+    match item_storage::retrieve_items(&storage_location(), &passphrase) {
         Ok(ref mut items) => {
-            if items.len() == 0 {
-                println!("No items in the database.");
+            let time = time_or_exit(&clock);
+
+            if let Some(tag) = tag {
+                items.retain(|item| item.has_tag(tag));
+            }
+
+            if format != OutputFormat::Text {
+                let records: Vec<CodeRecord> = items
+                    .iter()
+                    .map(|item| code_record(item, time, None))
+                    .collect();
+
+                match format {
+                    OutputFormat::Json => println!("{}", output::to_json(&records)),
+                    _ => print!("{}", output::to_tsv(&records)),
+                }
+
+                return;
+            }
+
+            if items.is_empty() {
+                match tag {
+                    Some(tag) => println!("No items are tagged '{}'.", tag),
+                    None => println!("No items in the database."),
+                }
             }
 
+            // Pad the labels, descriptions and codes so that the columns line up.
+            let width = items
+                .iter()
+                .map(|item| item.label.chars().count())
+                .max()
+                .unwrap_or(0);
+            let description_width = items
+                .iter()
+                .map(|item| item.description().chars().count())
+                .max()
+                .unwrap_or(0);
+            let code_width = items
+                .iter()
+                .map(|item| item.digits as usize)
+                .max()
+                .unwrap_or(0);
+
             for item in items {
-                match item.get_code() {
-                    Ok(code) => println!("{} - {}", item.label, code),
+                // Only call out the algorithm when it isn't the usual SHA-1.
+                let algorithm = if item.algorithm == Algorithm::Sha1 {
+                    String::new()
+                } else {
+                    format!(" ({})", item.algorithm)
+                };
+                let mut padding = " ".repeat(width - item.label.chars().count());
+
+                if description_width > 0 {
+                    let description = item.description();
+                    padding.push_str(&format!(
+                        "  {}{}",
+                        description,
+                        " ".repeat(description_width - description.chars().count())
+                    ));
+                }
+
+                if item.kind == Kind::Hotp {
+                    println!(
+                        "{}{} - HOTP (counter {}){}",
+                        item.label, padding, item.counter, algorithm
+                    );
+                    continue;
+                }
+
+                let code;
+                match item.get_code_at(time) {
+                    Ok(c) => code = c,
                     Err(e) => {
                         eprintln!("{}", e);
                         std::process::exit(1)
                     }
                 }
+
+                let remaining = item.remaining_seconds_at(time).unwrap_or(0);
+                let mut next = String::new();
+
+                if next_code_within.is_some_and(|seconds| remaining <= seconds) {
+                    match item.next_code_at(time) {
+                        Ok(c) => next = format!(" (next {})", c),
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            std::process::exit(1)
+                        }
+                    }
+                }
+
+                println!(
+                    "{}{} - {:<code_width$} {:>3}s{}{}",
+                    item.label, padding, code, remaining, algorithm, next
+                );
             }
         }
         Err(e) => {
@@ -118,10 +680,9 @@ pub fn run_new() {
         label = String::from(label.trim());
     }
 
-    let mut secret = String::new();
-    let mut is_valid = false;
+    let mut secret;
 
-    while !is_valid {
+    loop {
         secret = String::new();
         print!("Secret (base-32 formatted): ");
 
@@ -133,24 +694,24 @@ pub fn run_new() {
                 std::process::exit(1);
             }
         }
-        secret = String::from(secret.trim());
-        is_valid = is_base_32(&secret);
 
-        if !is_valid {
-            eprintln!("The secret must be a base-32 string.");
+        match normalize_secret(&secret) {
+            Ok(s) => {
+                secret = s;
+                break;
+            }
+            Err(e) => eprintln!("{}", e),
         }
-
-        is_valid = is_valid && !secret.is_empty();
     }
 
-    let mut digits;
+    let kind: Kind;
 
     loop {
-        digits = String::new();
-        print!("Number of digits(6/7/8, default: 6): ");
+        let mut input = String::new();
+        print!("Type(totp/hotp/steam, default: totp): ");
 
         let _ = stdout().flush();
-        match stdin().read_line(&mut digits) {
+        match stdin().read_line(&mut input) {
             Ok(_) => (),
             Err(_) => {
                 eprintln!("Could not retrieve user input.");
@@ -158,29 +719,29 @@ pub fn run_new() {
             }
         }
 
-        digits = String::from(digits.trim());
+        input = String::from(input.trim());
 
-        if digits.is_empty() {
-            digits = String::from("6");
+        if input.is_empty() {
+            input = String::from("totp");
         }
 
-        is_valid = digits == "6" || digits == "7" || digits == "8";
-
-        if !is_valid {
-            eprintln!("The number of digits must be 6, 7 or 8.");
-        } else {
-            break;
+        match input.parse::<Kind>() {
+            Ok(k) => {
+                kind = k;
+                break;
+            }
+            Err(e) => eprintln!("{}", e),
         }
     }
 
-    let mut period;
+    let algorithm: Algorithm;
 
     loop {
-        period = String::new();
-        print!("Token period(seconds, default: 30): ");
+        let mut input = String::new();
+        print!("Algorithm(sha1/sha256/sha512, default: sha1): ");
 
         let _ = stdout().flush();
-        match stdin().read_line(&mut period) {
+        match stdin().read_line(&mut input) {
             Ok(_) => (),
             Err(_) => {
                 eprintln!("Could not retrieve user input.");
@@ -188,65 +749,175 @@ pub fn run_new() {
             }
         }
 
-        period = String::from(period.trim());
+        input = String::from(input.trim());
 
-        if period.is_empty() {
-            period = String::from("30");
+        if input.is_empty() {
+            input = String::from("sha1");
         }
 
-        is_valid = is_number(&period) && period != "0";
-
-        if !is_valid {
-            eprintln!("The period must be number greater than 0.");
-        } else {
-            break;
+        match input.parse::<Algorithm>() {
+            Ok(a) => {
+                algorithm = a;
+                break;
+            }
+            Err(e) => eprintln!("{}", e),
         }
     }
 
-    println!("\nThe item to be added: ");
-    println!("Label: {}", label);
-    println!("Secret: {}", secret);
-    println!("Digits: {}", digits);
-    println!("Token period: {} seconds", period);
+    // Steam Guard codes always have the same length.
+    let mut digits = STEAM_CODE_LENGTH.to_string();
 
-    let mut confirm = String::new();
-
-    loop {
-        print!("Add to database (y/N) ");
+    if kind != Kind::Steam {
+        loop {
+            digits = String::new();
+            print!(
+                "Number of digits({}-{}, default: 6): ",
+                MIN_DIGITS, MAX_DIGITS
+            );
 
-        let _ = stdout().flush();
-        match stdin().read_line(&mut confirm) {
-            Ok(_) => (),
-            Err(_) => {
-                eprintln!("Could not retrieve user input.");
-                std::process::exit(1);
+            let _ = stdout().flush();
+            match stdin().read_line(&mut digits) {
+                Ok(_) => (),
+                Err(_) => {
+                    eprintln!("Could not retrieve user input.");
+                    std::process::exit(1);
+                }
             }
-        }
 
-        confirm = String::from(confirm.trim());
+            digits = String::from(digits.trim());
 
-        if confirm.to_lowercase() == "y" {
-            break;
-        } else if confirm.to_lowercase() == "n" {
-            std::process::exit(0);
+            if digits.is_empty() {
+                digits = String::from("6");
+            }
+
+            match parse_digits(&digits) {
+                Ok(_) => break,
+                Err(e) => eprintln!("{}", e),
+            }
         }
     }
 
-    let digits_enum: Digits;
-    if digits == "8" || digits == "eight" {
-        digits_enum = Digits::Eight;
-    } else if digits == "7" || digits == "seven" {
-        digits_enum = Digits::Seven;
-    } else {
-        digits_enum = Digits::Six;
-    }
+    let mut period = String::from("30");
+    let mut counter = String::from("0");
 
-    let period_num: u32;
+    if kind == Kind::Hotp {
+        loop {
+            counter = String::new();
+            print!("Initial counter(default: 0): ");
 
-    match period.parse::<u32>() {
-        Ok(d) => period_num = d,
-        Err(_) => {
-            eprintln!("Could not convert the supplied period into a number.");
+            let _ = stdout().flush();
+            match stdin().read_line(&mut counter) {
+                Ok(_) => (),
+                Err(_) => {
+                    eprintln!("Could not retrieve user input.");
+                    std::process::exit(1);
+                }
+            }
+
+            counter = String::from(counter.trim());
+
+            if counter.is_empty() {
+                counter = String::from("0");
+            }
+
+            if counter.parse::<u64>().is_err() {
+                eprintln!("The counter must be a positive number.");
+            } else {
+                break;
+            }
+        }
+    }
+
+    if kind.is_time_based() {
+        loop {
+            period = String::new();
+            print!("Token period(seconds, default: 30): ");
+
+            let _ = stdout().flush();
+            match stdin().read_line(&mut period) {
+                Ok(_) => (),
+                Err(_) => {
+                    eprintln!("Could not retrieve user input.");
+                    std::process::exit(1);
+                }
+            }
+
+            period = String::from(period.trim());
+
+            if period.is_empty() {
+                period = String::from("30");
+            }
+
+            if is_number(&period) && period != "0" {
+                break;
+            }
+
+            eprintln!("The period must be number greater than 0.");
+        }
+    }
+
+    println!("\nThe item to be added: ");
+    println!("Label: {}", label);
+    println!("Secret: {}", secret);
+    println!("Type: {}", kind);
+    println!("Algorithm: {}", algorithm);
+    println!("Digits: {}", digits);
+
+    if kind == Kind::Hotp {
+        println!("Counter: {}", counter);
+    } else {
+        println!("Token period: {} seconds", period);
+    }
+
+    let mut confirm = String::new();
+
+    loop {
+        print!("Add to database (y/N) ");
+
+        let _ = stdout().flush();
+        match stdin().read_line(&mut confirm) {
+            Ok(_) => (),
+            Err(_) => {
+                eprintln!("Could not retrieve user input.");
+                std::process::exit(1);
+            }
+        }
+
+        confirm = String::from(confirm.trim());
+
+        if confirm.to_lowercase() == "y" {
+            break;
+        } else if confirm.to_lowercase() == "n" {
+            std::process::exit(0);
+        }
+    }
+
+    let digits_num: u32;
+
+    match parse_digits(&digits) {
+        Ok(d) => digits_num = d,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+
+    let period_num: u32;
+
+    match period.parse::<u32>() {
+        Ok(d) => period_num = d,
+        Err(_) => {
+            eprintln!("Could not convert the supplied period into a number.");
+            std::process::exit(1);
+        }
+    }
+
+    let counter_num: u64;
+
+    match counter.parse::<u64>() {
+        Ok(c) => counter_num = c,
+        Err(_) => {
+            eprintln!("Could not convert the supplied counter into a number.");
             std::process::exit(1);
         }
     }
@@ -254,21 +925,381 @@ pub fn run_new() {
     let item = Item {
         label,
         secret,
-        digits: digits_enum,
+        digits: digits_num,
         split_time: period_num,
+        kind,
+        counter: counter_num,
+        algorithm,
+        issuer: None,
+        account: None,
+        tags: Vec::new(),
+        time_offset: 0,
+        t0: 0,
     };
 
+    add_item(item);
+
+    println!("\nSuccessfully added to database.");
+}
+
+/// Adds an item from command line options, without prompting for anything.
+pub fn run_add(label: &String, options: &ItemOptions) {
+    if options.secret.is_none() && options.secret_file.is_none() {
+        eprintln!("A secret is required, given with --secret or --secret-file.");
+        std::process::exit(1);
+    }
+
+    let mut item = Item {
+        label: label.clone(),
+        secret: String::new(),
+        digits: 6,
+        split_time: 30,
+        kind: Kind::Totp,
+        counter: 0,
+        algorithm: Algorithm::Sha1,
+        issuer: None,
+        account: None,
+        tags: Vec::new(),
+        time_offset: 0,
+        t0: 0,
+    };
+
+    match options.apply(&mut item) {
+        Ok(()) => (),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+
+    add_item(item);
+    println!("Successfully added '{}' to the database.", label);
+}
+
+pub fn run_add_uri(uri: &String) {
+    match uri::parse_uri(uri) {
+        Ok(item) => {
+            let label = item.label.clone();
+            add_item(item);
+            println!("Successfully added '{}' to the database.", label);
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(feature = "qr")]
+pub fn run_add_qr(path: &String) {
+    let text;
+    match qr::decode_image(path) {
+        Ok(t) => text = t,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+
+    // Google Authenticator exports are QR codes too.
+    if text.starts_with("otpauth-migration://") {
+        run_import_migration(&vec![text]);
+    } else {
+        run_add_uri(&text);
+    }
+}
+
+/// Imports the accounts of a Google Authenticator export. Each source is an
+/// otpauth-migration:// URI or, with the qr feature, an image of one of the export QR codes.
+pub fn run_import_migration(sources: &Vec<String>) {
+    let mut batches: Vec<migration::Batch> = Vec::new();
+
+    for source in sources {
+        let uri;
+
+        if source.starts_with("otpauth-migration://") {
+            uri = source.clone();
+        } else {
+            #[cfg(feature = "qr")]
+            match qr::decode_image(source) {
+                Ok(text) => uri = text,
+                Err(e) => {
+                    eprintln!("{}: {}", source, e);
+                    std::process::exit(1);
+                }
+            }
+
+            #[cfg(not(feature = "qr"))]
+            {
+                eprintln!("'{}' is not an otpauth-migration:// URI.", source);
+                std::process::exit(1);
+            }
+        }
+
+        match migration::parse_migration_uri(&uri) {
+            Ok(batch) => {
+                // The same QR code may have been scanned twice.
+                if !batches
+                    .iter()
+                    .any(|b| b.batch_id == batch.batch_id && b.batch_index == batch.batch_index)
+                {
+                    batches.push(batch);
+                }
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let mut reported_ids: Vec<u64> = Vec::new();
+
+    for batch in batches.iter() {
+        if reported_ids.contains(&batch.batch_id) {
+            continue;
+        }
+
+        reported_ids.push(batch.batch_id);
+        let parts = batches
+            .iter()
+            .filter(|b| b.batch_id == batch.batch_id)
+            .count() as u64;
+
+        if parts < batch.batch_size {
+            eprintln!(
+                "Only {} of the {} parts of the export were provided, the accounts in the missing parts will not be imported.",
+                parts, batch.batch_size
+            );
+        }
+    }
+
+    let mut items = Vec::new();
+    let mut skipped = Vec::new();
+
+    for batch in batches {
+        items.extend(batch.items);
+        skipped.extend(batch.skipped);
+    }
+
+    import_items(items, skipped);
+}
+
+pub fn run_import_aegis(path: &String) {
+    let contents;
+    match fs::read_to_string(path) {
+        Ok(c) => contents = c,
+        Err(e) => {
+            eprintln!("Could not read '{}': {}", path, e);
+            std::process::exit(1);
+        }
+    }
+
+    let mut password = None;
+
+    match aegis::is_encrypted(&contents) {
+        Ok(true) => {
+            match request_secret(AEGIS_PASSWORD_VARIABLE, "Aegis vault password: ", false) {
+                Ok(p) => password = Some(p),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Ok(false) => (),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+
+    match aegis::import_vault(&contents, password.as_deref()) {
+        Ok(import) => {
+            for (label, field) in import.ignored {
+                println!(
+                    "The {} of '{}' is not supported and was not imported.",
+                    field, label
+                );
+            }
+
+            import_items(import.items, import.skipped);
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+pub fn run_import_andotp(path: &String) {
+    let contents;
+    match fs::read_to_string(path) {
+        Ok(c) => contents = c,
+        Err(e) => {
+            eprintln!("Could not read '{}': {}", path, e);
+            std::process::exit(1);
+        }
+    }
+
+    match andotp::import_backup(&contents) {
+        Ok(import) => import_items(import.items, import.skipped),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Writes every item to an Aegis vault, encrypted with a new password if requested.
+pub fn run_export_aegis(output: &Option<String>, encrypt: bool) {
+    require_database();
+
+    let passphrase = database_passphrase();
+    let items;
+    {
+        // Don't hold the lock while the vault password is being typed.
+        let _lock = lock_database();
+        items = read_database(&passphrase);
+    }
+
+    let mut password = None;
+
+    if encrypt {
+        match request_secret(AEGIS_PASSWORD_VARIABLE, "New Aegis vault password: ", true) {
+            Ok(p) => password = Some(p),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    match aegis::export_vault(&items, password.as_deref()) {
+        Ok(export) => {
+            for (label, reason) in export.skipped {
+                eprintln!("Skipped '{}': {}.", label, reason);
+            }
+
+            for (label, field) in export.ignored {
+                eprintln!(
+                    "The {} of '{}' is not supported by Aegis and was not exported.",
+                    field, label
+                );
+            }
+
+            write_export(output, &export.vault, export.exported);
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+
+    if !encrypt {
+        eprintln!("The exported vault is not encrypted, delete it once it has been imported.");
+    }
+}
+
+/// Writes the otpauth:// URI of every item, one per line, to the output file or stdout.
+pub fn run_export_uris(output: &Option<String>) {
+    require_database();
+
+    let passphrase = database_passphrase();
+    let _lock = lock_database();
+    let items = read_database(&passphrase);
+    let mut uris = String::new();
+
+    for item in items.iter() {
+        uris.push_str(&uri::to_uri(item));
+        uris.push('\n');
+    }
+
+    write_export(output, &uris, items.len());
+    eprintln!("The exported URIs contain the secrets unencrypted, delete them once they have been imported.");
+}
+
+/// Adds imported items to the database, skipping any whose label is already taken, and reports
+/// every entry that was not imported.
+fn import_items(new_items: Vec<Item>, skipped: Vec<(String, String)>) {
+    let passphrase = database_passphrase();
+    let _lock = lock_database();
+    let mut items = Vec::new();
+
     if storage_location_exists() {
-        match item_storage::retrieve_items(&storage_location()) {
+        match item_storage::retrieve_items(&storage_location(), &passphrase) {
+            Ok(i) => items = i,
+            Err(e) => {
+                eprintln!("An error occurred when reading the database: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let mut imported = 0;
+
+    for (label, reason) in skipped {
+        println!("Skipped '{}': {}.", label, reason);
+    }
+
+    for item in new_items {
+        if contains_item_label(&item.label, &items) {
+            println!(
+                "Skipped '{}': an item with this label already exists.",
+                item.label
+            );
+            continue;
+        }
+
+        match item.validate() {
+            Ok(()) => (),
+            Err(e) => {
+                println!("Skipped '{}': {}", item.label, e);
+                continue;
+            }
+        }
+
+        items.push(item);
+        imported += 1;
+    }
+
+    if imported > 0 {
+        match item_storage::write_items(&storage_location(), &items, &passphrase) {
+            Ok(()) => (),
+            Err(e) => {
+                eprintln!("An error occurred when writing the database: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    println!("Imported {} items into the database.", imported);
+}
+
+/// Adds the item to the database, creating the database if it doesn't exist yet.
+fn add_item(item: Item) {
+    match item.validate() {
+        Ok(()) => (),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+
+    let passphrase = database_passphrase();
+    let _lock = lock_database();
+
+    if storage_location_exists() {
+        match item_storage::retrieve_items(&storage_location(), &passphrase) {
             Ok(ref mut items) => {
-                if contains_item_label(&item.label, &items) {
+                if contains_item_label(&item.label, items) {
                     eprintln!("An item with this label already exists.");
                     std::process::exit(1);
                 }
 
                 items.push(item);
 
-                match item_storage::write_items(&storage_location(), items) {
+                match item_storage::write_items(&storage_location(), items, &passphrase) {
                     Ok(()) => (),
                     Err(e) => {
                         eprintln!("An error occurred when writing the database: {}", e);
@@ -282,7 +1313,7 @@ pub fn run_new() {
             }
         }
     } else {
-        match item_storage::write_items(&storage_location(), &vec![item]) {
+        match item_storage::write_items(&storage_location(), &vec![item], &passphrase) {
             Ok(()) => (),
             Err(e) => {
                 eprintln!("An error occurred when writing the database: {}", e);
@@ -290,32 +1321,165 @@ pub fn run_new() {
             }
         }
     }
+}
 
-    println!("\nSuccessfully added to database.");
+/// Exports go to stdout unless an output file is given, so status messages go to stderr.
+fn write_export(output: &Option<String>, contents: &String, count: usize) {
+    match output {
+        Some(path) => match write_private(path, contents) {
+            Ok(()) => eprintln!("Exported {} items to '{}'.", count, path),
+            Err(e) => {
+                eprintln!("Could not write '{}': {}", path, e);
+                std::process::exit(1);
+            }
+        },
+        None => print!("{}", contents),
+    }
+}
+
+/// Writes a file that only its owner can read, as exports may contain every secret.
+fn write_private(path: &String, contents: &String) -> std::io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+
+        let file = options.open(path)?;
+        // The mode only applies to new files, an existing file keeps its own permissions.
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+        return (&file).write_all(contents.as_bytes());
+    }
+
+    #[cfg(not(unix))]
+    return options.open(path)?.write_all(contents.as_bytes());
+}
+
+/// Exits when there is no database yet, for the commands that work with existing items.
+fn require_database() {
+    if !storage_location_exists() {
+        eprintln!("No database file found. Please add an item first.");
+        std::process::exit(1);
+    }
 }
 
-pub fn run_startup_checks() -> Option<String> {
-    match dirs::home_dir() {
-        Some(mut path) => {
-            if !path.exists() {
-                return Some(String::from("The home directory does not exist"));
+fn code_record(item: &Item, time: u64, code: Option<String>) -> CodeRecord {
+    match CodeRecord::new(item, time, code) {
+        Ok(record) => return record,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// The clock that codes are generated with, fixed at the time given on the command line or the
+/// system clock corrected by the time offset setting.
+fn clock_or_exit(at: &Option<String>) -> Clock {
+    if let Some(at) = at {
+        match parse_timestamp(at) {
+            Ok(time) => return Clock::Fixed(time),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
             }
+        }
+    }
+
+    match settings::settings_location().and_then(|path| settings::read_settings(&path)) {
+        Ok(settings) => {
+            return Clock::System {
+                offset: settings.time_offset,
+            }
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn time_or_exit(clock: &Clock) -> u64 {
+    match clock.now() {
+        Ok(time) => return time,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn read_database(passphrase: &String) -> Vec<Item> {
+    match item_storage::retrieve_items(&storage_location(), passphrase) {
+        Ok(items) => return items,
+        Err(e) => {
+            eprintln!("An error occurred when reading the database: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn write_database(items: &Vec<Item>, passphrase: &String) {
+    match item_storage::write_items(&storage_location(), items, passphrase) {
+        Ok(()) => (),
+        Err(e) => {
+            eprintln!("An error occurred when writing the database: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
 
-            path.push(".otpc");
-            if path.exists() {
+/// Locks the database until the returned lock is dropped, so that other otpc processes don't
+/// overwrite the changes made in between.
+fn lock_database() -> item_storage::DatabaseLock {
+    match item_storage::lock_database(&storage_location()) {
+        Ok(lock) => return lock,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn database_passphrase() -> String {
+    match request_passphrase(!item_storage::is_encrypted(&storage_location())) {
+        Ok(passphrase) => return passphrase,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Chooses the database for this process, see item_storage::resolve_storage_location, and
+/// creates the data directory if it is used and doesn't exist yet.
+pub fn run_startup_checks(database: &Option<String>, vault: &Option<String>) -> Option<String> {
+    let path;
+    match item_storage::resolve_storage_location(database, vault) {
+        Ok(p) => path = p,
+        Err(e) => return Some(e),
+    }
+
+    item_storage::set_storage_location(path.clone());
+
+    match item_storage::data_directory() {
+        Ok(directory) => {
+            if directory.exists() || !Path::new(&path).starts_with(&directory) {
                 return None;
-            } else {
-                match fs::create_dir(path) {
-                    Ok(_) => return None,
-                    Err(e) => {
-                        return Some(format!(
-                            "An error occurred whilst making the storage directory: {}",
-                            e
-                        ))
-                    }
+            }
+
+            match fs::create_dir_all(directory) {
+                Ok(_) => return None,
+                Err(e) => {
+                    return Some(format!(
+                        "An error occurred whilst making the storage directory: {}",
+                        e
+                    ))
                 }
             }
         }
-        None => return Some(String::from("Could not determine home directory.")),
+        Err(e) => return Some(e),
     }
 }
@@ -0,0 +1,228 @@
+use crate::item::Algorithm;
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Steam Guard codes are written with these characters, which can't be mistaken for one another.
+const STEAM_ALPHABET: &'static [u8] = b"23456789BCDFGHJKMNPQRTVWXY";
+/// The number of characters in a Steam Guard code.
+pub const STEAM_CODE_LENGTH: u32 = 5;
+
+/// Generates an RFC 4226 HOTP code for the counter.
+pub fn hotp(
+    secret: &str,
+    counter: u64,
+    digits: u32,
+    algorithm: &Algorithm,
+) -> Result<String, String> {
+    let binary = truncated_hmac(secret, counter, algorithm)?;
+
+    return Ok(format!(
+        "{:0width$}",
+        binary as u64 % 10u64.pow(digits),
+        width = digits as usize
+    ));
+}
+
+/// Generates a Steam Guard code for a unix timestamp. Steam uses TOTP, but writes the code with
+/// its own alphabet instead of decimal digits.
+pub fn steam_at(
+    secret: &str,
+    period: u64,
+    time: u64,
+    algorithm: &Algorithm,
+) -> Result<String, String> {
+    if period == 0 {
+        return Err(String::from("The period must be greater than 0."));
+    }
+
+    let mut binary = truncated_hmac(secret, time / period, algorithm)?;
+    let mut code = String::new();
+
+    for _ in 0..STEAM_CODE_LENGTH {
+        code.push(STEAM_ALPHABET[(binary % STEAM_ALPHABET.len() as u32) as usize] as char);
+        binary /= STEAM_ALPHABET.len() as u32;
+    }
+
+    return Ok(code);
+}
+
+/// Generates an RFC 6238 TOTP code for a unix timestamp.
+pub fn totp_at(
+    secret: &str,
+    period: u64,
+    time: u64,
+    digits: u32,
+    algorithm: &Algorithm,
+) -> Result<String, String> {
+    if period == 0 {
+        return Err(String::from("The period must be greater than 0."));
+    }
+
+    return hotp(secret, time / period, digits, algorithm);
+}
+
+/// Where the time that codes are generated for comes from. The system clock can be corrected by
+/// an offset in seconds, or replaced by a fixed unix timestamp to reproduce the codes of a moment.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Clock {
+    System { offset: i64 },
+    Fixed(u64),
+}
+
+impl Clock {
+    pub fn now(&self) -> Result<u64, String> {
+        match self {
+            Clock::System { offset } => return Ok(current_time()?.saturating_add_signed(*offset)),
+            Clock::Fixed(time) => return Ok(*time),
+        }
+    }
+}
+
+pub fn current_time() -> Result<u64, String> {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(d) => return Ok(d.as_secs()),
+        Err(_) => return Err(String::from("The system time is before the unix epoch.")),
+    }
+}
+
+/// Computes the HMAC of the counter and applies the dynamic truncation of RFC 4226, giving a 31-bit
+/// number that the code is formed from.
+fn truncated_hmac(secret: &str, counter: u64, algorithm: &Algorithm) -> Result<u32, String> {
+    let key = decode_secret(secret)?;
+    let hash = hmac(&key, &counter.to_be_bytes(), algorithm);
+
+    // The low nibble of the last byte selects the 4 bytes to use.
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    return Ok(((hash[offset] as u32 & 0x7f) << 24)
+        | ((hash[offset + 1] as u32) << 16)
+        | ((hash[offset + 2] as u32) << 8)
+        | (hash[offset + 3] as u32));
+}
+
+fn hmac(key: &[u8], message: &[u8], algorithm: &Algorithm) -> Vec<u8> {
+    // HMAC accepts keys of any length so creating the MAC cannot fail.
+    match algorithm {
+        Algorithm::Sha1 => {
+            let mut mac = Hmac::<Sha1>::new_from_slice(key).unwrap();
+            mac.update(message);
+            return mac.finalize().into_bytes().to_vec();
+        }
+        Algorithm::Sha256 => {
+            let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+            mac.update(message);
+            return mac.finalize().into_bytes().to_vec();
+        }
+        Algorithm::Sha512 => {
+            let mut mac = Hmac::<Sha512>::new_from_slice(key).unwrap();
+            mac.update(message);
+            return mac.finalize().into_bytes().to_vec();
+        }
+    }
+}
+
+fn decode_secret(secret: &str) -> Result<Vec<u8>, String> {
+    match base32::decode(base32::Alphabet::RFC4648 { padding: false }, secret) {
+        Some(key) => return Ok(key),
+        None => return Err(String::from("The secret is not a valid base-32 string.")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    // The ASCII secret "12345678901234567890" used by the RFC test vectors.
+    const RFC_SECRET: &'static str = "gezdgnbvgy3tqojqgezdgnbvgy3tqojq";
+
+    #[test]
+    pub fn test_hotp() {
+        use super::*;
+        let expected = ["755224", "287082", "359152", "969429", "338314"];
+
+        for (counter, code) in expected.iter().enumerate() {
+            assert_eq!(
+                &hotp(RFC_SECRET, counter as u64, 6, &Algorithm::Sha1).unwrap(),
+                code
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_totp_at() {
+        use super::*;
+        assert_eq!(
+            totp_at(RFC_SECRET, 30, 59, 8, &Algorithm::Sha1).unwrap(),
+            "94287082"
+        );
+        assert_eq!(
+            totp_at(RFC_SECRET, 30, 1111111109, 8, &Algorithm::Sha1).unwrap(),
+            "07081804"
+        );
+    }
+
+    #[test]
+    pub fn test_totp_at_sha256() {
+        use super::*;
+        let secret = "gezdgnbvgy3tqojqgezdgnbvgy3tqojqgezdgnbvgy3tqojqgeza";
+
+        assert_eq!(
+            totp_at(secret, 30, 59, 8, &Algorithm::Sha256).unwrap(),
+            "46119246"
+        );
+        assert_eq!(
+            totp_at(secret, 30, 1111111109, 8, &Algorithm::Sha256).unwrap(),
+            "68084774"
+        );
+    }
+
+    #[test]
+    pub fn test_totp_at_sha512() {
+        use super::*;
+        let secret = "gezdgnbvgy3tqojqgezdgnbvgy3tqojqgezdgnbvgy3tqojqgezdgnbvgy3tqojqgezdgnbvgy3tqojqgezdgnbvgy3tqojqgezdgna";
+
+        assert_eq!(
+            totp_at(secret, 30, 59, 8, &Algorithm::Sha512).unwrap(),
+            "90693936"
+        );
+        assert_eq!(
+            totp_at(secret, 30, 1111111109, 8, &Algorithm::Sha512).unwrap(),
+            "25091201"
+        );
+    }
+
+    #[test]
+    pub fn test_steam_at() {
+        use super::*;
+        assert_eq!(
+            steam_at(RFC_SECRET, 30, 59, &Algorithm::Sha1).unwrap(),
+            "PV9M4"
+        );
+        assert_eq!(
+            steam_at(RFC_SECRET, 30, 1111111109, &Algorithm::Sha1).unwrap(),
+            "PY4YB"
+        );
+        assert!(steam_at(RFC_SECRET, 0, 59, &Algorithm::Sha1).is_err());
+    }
+
+    #[test]
+    pub fn test_clock() {
+        use super::*;
+        assert_eq!(Clock::Fixed(59).now(), Ok(59));
+
+        let system = Clock::System { offset: 0 }.now().unwrap();
+        let ahead = Clock::System { offset: 3600 }.now().unwrap();
+        assert!(ahead >= system + 3600 && ahead <= system + 3601);
+    }
+
+    #[test]
+    pub fn test_totp_at_zero_period() {
+        use super::*;
+        assert!(totp_at(RFC_SECRET, 0, 59, 6, &Algorithm::Sha1).is_err());
+    }
+
+    #[test]
+    pub fn test_hotp_invalid_secret() {
+        use super::*;
+        assert!(hotp("abc1", 0, 6, &Algorithm::Sha1).is_err());
+    }
+}
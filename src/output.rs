@@ -0,0 +1,203 @@
+use crate::item::{Item, Kind};
+use serde::Serialize;
+
+// The field names and order are relied upon by scripts, so new fields only go at the end.
+const TSV_FIELDS: [&'static str; 11] = [
+    "label",
+    "issuer",
+    "type",
+    "code",
+    "remaining_seconds",
+    "period",
+    "digits",
+    "next_code",
+    "counter",
+    "account",
+    "tags",
+];
+
+/// The fields printed for an item by the json and tsv output formats.
+#[derive(Serialize, PartialEq, Debug)]
+pub struct CodeRecord {
+    pub label: String,
+    pub issuer: Option<String>,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub code: Option<String>,
+    pub remaining_seconds: Option<u64>,
+    pub period: Option<u32>,
+    pub digits: u32,
+    pub next_code: Option<String>,
+    pub counter: Option<u64>,
+    pub account: Option<String>,
+    pub tags: Vec<String>,
+}
+
+impl CodeRecord {
+    /// Builds the record of a TOTP item at the timestamp.
+    fn totp(item: &Item, time: u64) -> Result<CodeRecord, String> {
+        return Ok(CodeRecord {
+            label: item.label.clone(),
+            issuer: item.issuer.clone(),
+            kind: item.kind.to_string(),
+            code: Some(item.get_code_at(time)?),
+            remaining_seconds: item.remaining_seconds_at(time),
+            period: Some(item.split_time),
+            digits: item.digits,
+            next_code: Some(item.next_code_at(time)?),
+            counter: None,
+            account: item.account.clone(),
+            tags: item.tags.clone(),
+        });
+    }
+
+    /// Builds the record of a HOTP item. The code is the one just generated, if any, in which
+    /// case the counter has already been advanced to that of the next code.
+    fn hotp(item: &Item, time: u64, code: Option<String>) -> Result<CodeRecord, String> {
+        let next_code = match code {
+            Some(_) => Some(item.get_code_at(time)?),
+            None => None,
+        };
+
+        return Ok(CodeRecord {
+            label: item.label.clone(),
+            issuer: item.issuer.clone(),
+            kind: item.kind.to_string(),
+            code,
+            remaining_seconds: None,
+            period: None,
+            digits: item.digits,
+            next_code,
+            counter: Some(item.counter),
+            account: item.account.clone(),
+            tags: item.tags.clone(),
+        });
+    }
+
+    /// Builds the record of an item at the timestamp, see totp and hotp.
+    pub fn new(item: &Item, time: u64, code: Option<String>) -> Result<CodeRecord, String> {
+        match item.kind {
+            Kind::Totp | Kind::Steam => return CodeRecord::totp(item, time),
+            Kind::Hotp => return CodeRecord::hotp(item, time, code),
+        }
+    }
+}
+
+pub fn to_json<T: Serialize>(value: &T) -> String {
+    // The records only contain strings and numbers, so serializing cannot fail.
+    return serde_json::to_string_pretty(value).unwrap();
+}
+
+/// Formats the records as tab separated values with a header row. Empty fields are left blank.
+pub fn to_tsv(records: &Vec<CodeRecord>) -> String {
+    let mut output = TSV_FIELDS.join("\t");
+    output.push('\n');
+
+    for record in records {
+        let fields = [
+            record.label.clone(),
+            record.issuer.clone().unwrap_or_default(),
+            record.kind.clone(),
+            record.code.clone().unwrap_or_default(),
+            optional(record.remaining_seconds),
+            optional(record.period),
+            record.digits.to_string(),
+            record.next_code.clone().unwrap_or_default(),
+            optional(record.counter),
+            record.account.clone().unwrap_or_default(),
+            record.tags.join(","),
+        ];
+
+        // Tabs and newlines would break the columns.
+        let fields: Vec<String> = fields
+            .iter()
+            .map(|f| f.replace(['\t', '\n', '\r'], " "))
+            .collect();
+
+        output.push_str(&fields.join("\t"));
+        output.push('\n');
+    }
+
+    return output;
+}
+
+fn optional<T: ToString>(value: Option<T>) -> String {
+    match value {
+        Some(v) => return v.to_string(),
+        None => return String::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::item::{Item, Kind};
+
+    fn item(kind: Kind) -> Item {
+        return Item {
+            label: String::from("Example:alice"),
+            secret: String::from("gezdgnbvgy3tqojqgezdgnbvgy3tqojq"),
+            digits: 8,
+            kind,
+            counter: 1,
+            issuer: Some(String::from("Example\tCo")),
+            account: Some(String::from("alice")),
+            tags: vec![String::from("work"), String::from("code")],
+            ..Default::default()
+        };
+    }
+
+    #[test]
+    pub fn test_code_record_totp() {
+        use super::*;
+        let record = CodeRecord::new(&item(Kind::Totp), 59, None).unwrap();
+
+        assert_eq!(record.code, Some(String::from("94287082")));
+        assert_eq!(record.remaining_seconds, Some(1));
+        assert_eq!(record.period, Some(30));
+        assert_eq!(
+            record.next_code,
+            Some(item(Kind::Totp).get_code_at(60).unwrap())
+        );
+        assert_eq!(record.counter, None);
+    }
+
+    #[test]
+    pub fn test_code_record_hotp() {
+        use super::*;
+        let mut hotp = item(Kind::Hotp);
+        let code = hotp.generate_code(59).unwrap();
+        let record = CodeRecord::new(&hotp, 59, Some(code)).unwrap();
+
+        assert_eq!(record.code, Some(String::from("94287082")));
+        assert_eq!(record.next_code, Some(String::from("37359152")));
+        assert_eq!(record.remaining_seconds, None);
+        assert_eq!(record.counter, Some(2));
+    }
+
+    #[test]
+    pub fn test_to_json() {
+        use super::*;
+        let record = CodeRecord::new(&item(Kind::Totp), 59, None).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&to_json(&record)).unwrap();
+
+        assert_eq!(json["label"], "Example:alice");
+        assert_eq!(json["type"], "totp");
+        assert_eq!(json["code"], "94287082");
+        assert_eq!(json["remaining_seconds"], 1);
+        assert_eq!(json["digits"], 8);
+        assert_eq!(json["tags"], serde_json::json!(["work", "code"]));
+        assert!(json["counter"].is_null());
+    }
+
+    #[test]
+    pub fn test_to_tsv() {
+        use super::*;
+        let records = vec![CodeRecord::new(&item(Kind::Hotp), 59, None).unwrap()];
+
+        assert_eq!(
+            to_tsv(&records),
+            "label\tissuer\ttype\tcode\tremaining_seconds\tperiod\tdigits\tnext_code\tcounter\taccount\ttags\n\
+             Example:alice\tExample Co\thotp\t\t\t\t8\t\t1\talice\twork,code\n"
+        );
+    }
+}
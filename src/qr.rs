@@ -0,0 +1,130 @@
+// Decoding the QR codes of enrollment screenshots, using rqrr to locate and read the code.
+
+/// Decodes the QR code in a PNG or JPEG image, returning the text it contains.
+pub fn decode_image(path: &String) -> Result<String, String> {
+    let image;
+    match image::open(path) {
+        Ok(i) => image = i.to_luma8(),
+        Err(e) => return Err(format!("Could not open the image: {}", e)),
+    }
+
+    return decode_luma(
+        image.width() as usize,
+        image.height() as usize,
+        image.as_raw(),
+    );
+}
+
+/// Decodes the QR code in an 8-bit grayscale image stored row by row.
+pub fn decode_luma(width: usize, height: usize, pixels: &[u8]) -> Result<String, String> {
+    if width == 0 || height == 0 || pixels.len() < width * height {
+        return Err(String::from("The image is empty."));
+    }
+
+    let mut image =
+        rqrr::PreparedImage::prepare_from_greyscale(width, height, |x, y| pixels[y * width + x]);
+    let grids = image.detect_grids();
+
+    if grids.is_empty() {
+        return Err(String::from("No QR code was found in the image."));
+    }
+
+    let mut last_error = String::new();
+
+    for grid in grids.iter() {
+        match grid.decode() {
+            Ok((_, text)) => return Ok(text),
+            Err(e) => last_error = format!("The QR code could not be read: {}", e),
+        }
+    }
+
+    return Err(last_error);
+}
+
+#[cfg(test)]
+mod test {
+    use qrcode::{Color, EcLevel, QrCode};
+
+    // Renders a code with a four module quiet zone, as a screenshot of it would look.
+    fn render(code: &QrCode, scale: usize) -> (usize, Vec<u8>) {
+        let width = code.width();
+        let colors = code.to_colors();
+        let size = (width + 8) * scale;
+        let mut pixels = vec![255u8; size * size];
+
+        for y in 0..width {
+            for x in 0..width {
+                if colors[y * width + x] == Color::Dark {
+                    for dy in 0..scale {
+                        for dx in 0..scale {
+                            pixels[((y + 4) * scale + dy) * size + (x + 4) * scale + dx] = 0;
+                        }
+                    }
+                }
+            }
+        }
+
+        return (size, pixels);
+    }
+
+    const URI: &'static str =
+        "otpauth://totp/Example:alice@example.com?secret=JBSWY3DPEHPK3PXP&issuer=Example";
+
+    #[test]
+    pub fn test_decode_luma() {
+        use super::*;
+        let (size, pixels) = render(&QrCode::new(URI).unwrap(), 4);
+
+        assert_eq!(decode_luma(size, size, &pixels).unwrap(), URI);
+    }
+
+    #[test]
+    pub fn test_decode_luma_large_version() {
+        use super::*;
+        let text = URI.repeat(6);
+        let (size, pixels) = render(
+            &QrCode::with_error_correction_level(&text, EcLevel::H).unwrap(),
+            3,
+        );
+
+        assert_eq!(decode_luma(size, size, &pixels).unwrap(), text);
+    }
+
+    #[test]
+    pub fn test_decode_luma_rotated() {
+        use super::*;
+        let (size, pixels) = render(&QrCode::new(URI).unwrap(), 4);
+        let mut rotated = vec![0u8; size * size];
+
+        for y in 0..size {
+            for x in 0..size {
+                rotated[x * size + (size - 1 - y)] = pixels[y * size + x];
+            }
+        }
+
+        assert_eq!(decode_luma(size, size, &rotated).unwrap(), URI);
+    }
+
+    #[test]
+    pub fn test_decode_luma_damaged() {
+        use super::*;
+        let code = QrCode::with_error_correction_level(URI, EcLevel::M).unwrap();
+        let (size, mut pixels) = render(&code, 4);
+        let center = size / 2;
+
+        // Invert a small patch of modules in the middle of the data.
+        for y in (center - 4)..(center + 4) {
+            for x in (center - 4)..(center + 4) {
+                pixels[y * size + x] = 255 - pixels[y * size + x];
+            }
+        }
+
+        assert_eq!(decode_luma(size, size, &pixels).unwrap(), URI);
+    }
+
+    #[test]
+    pub fn test_decode_luma_no_code() {
+        use super::*;
+        assert!(decode_luma(10, 10, &[255u8; 100]).is_err());
+    }
+}
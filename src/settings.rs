@@ -0,0 +1,77 @@
+// Settings that apply to every vault on this machine, such as correcting a skewed system clock.
+// They hold nothing secret, so they are kept unencrypted in settings.json in the data directory.
+
+use crate::item_storage::{data_directory, replace_file};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+const SETTINGS_FILE: &'static str = "settings.json";
+
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug, Default)]
+pub struct Settings {
+    /// Seconds added to the system clock when generating TOTP codes.
+    #[serde(default)]
+    pub time_offset: i64,
+}
+
+pub fn settings_location() -> Result<String, String> {
+    let mut path = data_directory()?;
+    path.push(SETTINGS_FILE);
+
+    match path.to_str() {
+        Some(p) => return Ok(String::from(p)),
+        None => return Err(String::from("The data directory is not valid unicode.")),
+    }
+}
+
+/// Reads the settings, which are the defaults until some have been saved.
+pub fn read_settings(path: &String) -> Result<Settings, String> {
+    if !Path::new(path).exists() {
+        return Ok(Settings::default());
+    }
+
+    let contents;
+    match fs::read_to_string(path) {
+        Ok(c) => contents = c,
+        Err(e) => return Err(format!("Could not read the settings: {}", e)),
+    }
+
+    match serde_json::from_str(&contents) {
+        Ok(settings) => return Ok(settings),
+        Err(e) => return Err(format!("The settings file is invalid: {}", e)),
+    }
+}
+
+pub fn write_settings(path: &String, settings: &Settings) -> Result<(), String> {
+    if let Some(directory) = Path::new(path).parent() {
+        if let Err(e) = fs::create_dir_all(directory) {
+            return Err(format!("Could not create the data directory: {}", e));
+        }
+    }
+
+    match serde_json::to_string_pretty(settings) {
+        Ok(contents) => return replace_file(path, contents.as_bytes(), "settings"),
+        Err(e) => return Err(format!("Could not encode the settings: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    pub fn test_settings() {
+        use super::*;
+        let directory =
+            std::env::temp_dir().join(format!("otpc-settings-test-{}", std::process::id()));
+        let path = String::from(directory.join(SETTINGS_FILE).to_str().unwrap());
+
+        assert_eq!(read_settings(&path), Ok(Settings::default()));
+
+        let settings = Settings { time_offset: -90 };
+        write_settings(&path, &settings).unwrap();
+        assert_eq!(read_settings(&path), Ok(settings));
+        assert!(!Path::new(&format!("{}.tmp", path)).exists());
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,246 @@
+use crate::item::{parse_digits, Algorithm, Item, Kind};
+use crate::otp::STEAM_CODE_LENGTH;
+use crate::util::normalize_secret;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use url::Url;
+
+const URI_SCHEME: &'static str = "otpauth";
+
+// Everything but the unreserved characters of RFC 3986 is escaped.
+const ESCAPED: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Parses an otpauth:// URI, as found in enrollment QR codes, into an item. The label is
+/// taken from the URI path, which is usually "Issuer:account".
+pub fn parse_uri(uri: &str) -> Result<Item, String> {
+    let url;
+    match Url::parse(uri.trim()) {
+        Ok(u) => url = u,
+        Err(e) => return Err(format!("The URI is malformed: {}", e)),
+    }
+
+    if url.scheme() != URI_SCHEME {
+        return Err(String::from("The URI must start with otpauth://"));
+    }
+
+    let mut kind;
+    match url.host_str() {
+        Some(host) => kind = host.parse::<Kind>()?,
+        None => return Err(String::from("The URI does not specify totp or hotp.")),
+    }
+
+    let path;
+    match percent_decode_str(url.path().trim_start_matches('/')).decode_utf8() {
+        Ok(p) => path = p.trim().to_string(),
+        Err(_) => return Err(String::from("The URI label is not valid UTF-8.")),
+    }
+
+    if path.is_empty() {
+        return Err(String::from("The URI does not contain a label."));
+    }
+
+    let label = path.split_whitespace().collect::<Vec<&str>>().join(" ");
+
+    // The issuer parameter takes precedence over an "Issuer:" prefix on the label.
+    let (mut issuer, account) = match path.split_once(':') {
+        Some((prefix, name)) if !prefix.trim().is_empty() => {
+            (Some(String::from(prefix.trim())), name.trim())
+        }
+        _ => (None, path.as_str()),
+    };
+
+    let mut secret = None;
+    let mut digits = 6;
+    let mut period = 30;
+    let mut counter = None;
+    let mut algorithm = Algorithm::Sha1;
+    let mut t0 = 0;
+
+    for (key, value) in url.query_pairs() {
+        match key.as_ref() {
+            "secret" => secret = Some(normalize_secret(&value)?),
+            "issuer" if !value.trim().is_empty() => {
+                issuer = Some(String::from(value.trim()));
+            }
+            "algorithm" => algorithm = value.parse::<Algorithm>()?,
+            "digits" => digits = parse_digits(&value)?,
+            "period" => match value.parse::<u32>() {
+                Ok(p) if p > 0 => period = p,
+                _ => return Err(String::from("The period must be number greater than 0.")),
+            },
+            "counter" => match value.parse::<u64>() {
+                Ok(c) => counter = Some(c),
+                Err(_) => return Err(String::from("The counter must be a positive number.")),
+            },
+            // Some clients mark Steam Guard items this way rather than with a steam:// host.
+            "encoder" if value.eq_ignore_ascii_case("steam") => kind = Kind::Steam,
+            // Not part of the usual format, but some services count their periods from a T0.
+            "t0" => match value.parse::<u64>() {
+                Ok(t) => t0 = t,
+                Err(_) => return Err(String::from("The T0 must be a unix timestamp.")),
+            },
+            _ => (),
+        }
+    }
+
+    let secret = match secret {
+        Some(s) => s,
+        None => return Err(String::from("The URI does not contain a secret.")),
+    };
+
+    if kind == Kind::Steam {
+        digits = STEAM_CODE_LENGTH;
+    }
+
+    if kind == Kind::Hotp && counter.is_none() {
+        return Err(String::from("A HOTP URI must contain a counter."));
+    }
+
+    return Ok(Item {
+        label,
+        secret,
+        digits,
+        split_time: period,
+        kind,
+        counter: counter.unwrap_or(0),
+        algorithm,
+        issuer,
+        account: if account.is_empty() {
+            None
+        } else {
+            Some(String::from(account))
+        },
+        tags: Vec::new(),
+        time_offset: 0,
+        t0,
+    });
+}
+
+/// Builds the otpauth:// URI of an item, so that it can be transferred to another client. Other
+/// clients expect the label to be "Issuer:account", so that is used when the account is known.
+pub fn to_uri(item: &Item) -> String {
+    let label = match (&item.issuer, &item.account) {
+        (Some(issuer), Some(account)) => format!("{}:{}", issuer, account),
+        (None, Some(account)) => account.clone(),
+        _ => item.label.clone(),
+    };
+
+    let mut uri = format!(
+        "{}://{}/{}?secret={}",
+        URI_SCHEME,
+        item.kind,
+        utf8_percent_encode(&label, ESCAPED),
+        item.secret.to_uppercase()
+    );
+
+    if let Some(issuer) = &item.issuer {
+        uri.push_str(&format!("&issuer={}", utf8_percent_encode(issuer, ESCAPED)));
+    }
+
+    uri.push_str(&format!(
+        "&algorithm={}&digits={}",
+        item.algorithm, item.digits
+    ));
+
+    match item.kind {
+        Kind::Totp | Kind::Steam => uri.push_str(&format!("&period={}", item.split_time)),
+        Kind::Hotp => uri.push_str(&format!("&counter={}", item.counter)),
+    }
+
+    if item.kind.is_time_based() && item.t0 != 0 {
+        uri.push_str(&format!("&t0={}", item.t0));
+    }
+
+    return uri;
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    pub fn test_parse_uri() {
+        use super::*;
+        let item = parse_uri(
+            "otpauth://totp/ACME%20Co:john.doe@email.com?secret=HXDMVJECJJWSRB3HWIZR4IFUGFTMXBOZ&issuer=ACME%20Co&algorithm=SHA256&digits=8&period=60",
+        )
+        .unwrap();
+
+        assert_eq!(item.label, "ACME Co:john.doe@email.com");
+        assert_eq!(item.issuer, Some(String::from("ACME Co")));
+        assert_eq!(item.account, Some(String::from("john.doe@email.com")));
+        assert_eq!(item.secret, "hxdmvjecjjwsrb3hwizr4ifugftmxboz");
+        assert_eq!(item.algorithm, Algorithm::Sha256);
+        assert_eq!(item.digits, 8);
+        assert_eq!(item.split_time, 60);
+        assert_eq!(item.kind, Kind::Totp);
+    }
+
+    #[test]
+    pub fn test_parse_uri_defaults() {
+        use super::*;
+        let item = parse_uri("otpauth://totp/Example:alice?secret=JBSWY3DPEHPK3PXP").unwrap();
+
+        assert_eq!(item.issuer, Some(String::from("Example")));
+        assert_eq!(item.algorithm, Algorithm::Sha1);
+        assert_eq!(item.digits, 6);
+        assert_eq!(item.split_time, 30);
+    }
+
+    #[test]
+    pub fn test_parse_uri_t0() {
+        use super::*;
+        let item =
+            parse_uri("otpauth://totp/alice?secret=JBSWY3DPEHPK3PXP&period=60&t0=1000").unwrap();
+
+        assert_eq!(item.t0, 1000);
+        assert!(to_uri(&item).ends_with("&period=60&t0=1000"));
+        assert_eq!(parse_uri(&to_uri(&item)).unwrap(), item);
+        assert!(parse_uri("otpauth://totp/alice?secret=JBSWY3DPEHPK3PXP&t0=-5").is_err());
+    }
+
+    #[test]
+    pub fn test_parse_uri_steam() {
+        use super::*;
+        let item = parse_uri("otpauth://steam/Steam:gabe?secret=JBSWY3DPEHPK3PXP").unwrap();
+
+        assert_eq!(item.kind, Kind::Steam);
+        assert_eq!(item.digits, 5);
+        assert_eq!(parse_uri(&to_uri(&item)).unwrap(), item);
+
+        let encoder =
+            parse_uri("otpauth://totp/Steam:gabe?secret=JBSWY3DPEHPK3PXP&encoder=steam").unwrap();
+        assert_eq!(encoder, item);
+    }
+
+    #[test]
+    pub fn test_parse_uri_hotp() {
+        use super::*;
+        let item = parse_uri("otpauth://hotp/alice?secret=JBSWY3DPEHPK3PXP&counter=5").unwrap();
+
+        assert_eq!(item.kind, Kind::Hotp);
+        assert_eq!(item.counter, 5);
+        assert!(parse_uri("otpauth://hotp/alice?secret=JBSWY3DPEHPK3PXP").is_err());
+    }
+
+    #[test]
+    pub fn test_parse_uri_fail() {
+        use super::*;
+        assert!(parse_uri("https://totp/alice?secret=JBSWY3DPEHPK3PXP").is_err());
+        assert!(parse_uri("otpauth://totp/alice").is_err());
+        assert!(parse_uri("otpauth://totp/alice?secret=abc1").is_err());
+        assert!(parse_uri("otpauth://totp/alice?secret=JBSWY3DPEHPK3PXP&digits=11").is_err());
+    }
+
+    #[test]
+    pub fn test_to_uri_round_trip() {
+        use super::*;
+        let item = parse_uri(
+            "otpauth://hotp/Example:alice?secret=JBSWY3DPEHPK3PXP&issuer=Example&algorithm=SHA512&digits=7&counter=42",
+        )
+        .unwrap();
+
+        assert_eq!(parse_uri(&to_uri(&item)).unwrap(), item);
+    }
+}
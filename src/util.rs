@@ -1,6 +1,7 @@
 use crate::item::Item;
 
 const BASE_32_ALPHABET: &'static str = "abcdefghijklmnopqrstuvwxyz234567";
+const PASSPHRASE_VARIABLE: &'static str = "OTPC_PASSPHRASE";
 
 pub fn is_base_32(str: &String) -> bool {
     for c in str.chars() {
@@ -12,8 +13,45 @@ pub fn is_base_32(str: &String) -> bool {
     return true;
 }
 
+#[cfg_attr(not(feature = "interactive"), allow(dead_code))]
 pub fn is_base_32_c(c: char) -> bool {
-    return BASE_32_ALPHABET.contains(c);
+    return BASE_32_ALPHABET.contains(c.to_ascii_lowercase());
+}
+
+/// Whether a character may be typed into a secret, which includes the separators and padding
+/// that normalize_secret removes.
+#[cfg_attr(not(feature = "interactive"), allow(dead_code))]
+pub fn is_secret_c(c: char) -> bool {
+    return is_base_32_c(c) || c == ' ' || c == '-' || c == '=';
+}
+
+/// Converts a base-32 secret as providers present it, in uppercase, grouped with spaces or
+/// dashes, or padded, into the lowercase unpadded form that items store.
+pub fn normalize_secret(secret: &str) -> Result<String, String> {
+    let secret = secret
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .collect::<String>()
+        .to_lowercase();
+    let secret = String::from(secret.trim_end_matches('='));
+
+    if secret.is_empty() {
+        return Err(String::from("The secret is empty."));
+    }
+
+    if !is_base_32(&secret) {
+        return Err(String::from("The secret must be a base-32 string."));
+    }
+
+    // Base-32 encodes 5 bits per character, so no whole number of bytes gives these lengths.
+    match secret.len() % 8 {
+        1 | 3 | 6 => {
+            return Err(String::from(
+                "The secret has an invalid length for a base-32 string.",
+            ))
+        }
+        _ => return Ok(secret),
+    }
 }
 
 pub fn is_number(str: &String) -> bool {
@@ -26,9 +64,9 @@ pub fn is_number(str: &String) -> bool {
     return true;
 }
 
-pub fn contains_white_space(str: &String) -> bool {
-    for c in str.chars() {
-        if c.is_whitespace() {
+pub fn contains_item_label(label: &String, items: &Vec<Item>) -> bool {
+    for ref lbl in items.iter().map(|item| item.label.clone()) {
+        if lbl == label {
             return true;
         }
     }
@@ -36,14 +74,344 @@ pub fn contains_white_space(str: &String) -> bool {
     return false;
 }
 
-pub fn contains_item_label(label: &String, items: &Vec<Item>) -> bool {
-    for ref lbl in items.into_iter().map(|item| item.label.clone()) {
-        if lbl == label {
-            return true;
+/// Builds an item label from the account name and issuer of an imported entry, as
+/// "Issuer:name" with runs of whitespace collapsed to single spaces.
+pub fn import_label(issuer: &str, name: &str) -> String {
+    let full_name = if !issuer.is_empty() && !name.starts_with(&format!("{}:", issuer)) {
+        format!("{}:{}", issuer, name)
+    } else {
+        String::from(name)
+    };
+
+    return full_name
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ");
+}
+
+/// Parses a timestamp given on the command line, either as unix seconds or in the RFC 3339 format,
+/// e.g. 2024-05-01T12:00:00Z or 2024-05-01T14:00:00+02:00, into unix seconds.
+pub fn parse_timestamp(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let invalid = || {
+        return format!(
+            "'{}' is not a unix timestamp or an RFC 3339 time such as 2024-05-01T12:00:00Z.",
+            text
+        );
+    };
+
+    if is_number(&String::from(text)) {
+        return text.parse::<u64>().map_err(|_| invalid());
+    }
+
+    let bytes = text.as_bytes();
+    if bytes.len() < 20
+        || !text.is_ascii()
+        || bytes[4] != b'-'
+        || bytes[7] != b'-'
+        || !matches!(bytes[10], b'T' | b't' | b' ')
+        || bytes[13] != b':'
+        || bytes[16] != b':'
+    {
+        return Err(invalid());
+    }
+
+    let number = |range: std::ops::Range<usize>| -> Result<i64, String> {
+        let part = &text[range];
+
+        if !part.chars().all(|c| c.is_ascii_digit()) {
+            return Err(invalid());
+        }
+
+        return part.parse::<i64>().map_err(|_| invalid());
+    };
+
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+    let (hour, minute, second) = (number(11..13)?, number(14..16)?, number(17..19)?);
+
+    // Fractions of a second don't affect the time step.
+    let mut rest = &text[19..];
+    if rest.starts_with('.') {
+        let digits = rest[1..].chars().take_while(|c| c.is_ascii_digit()).count();
+
+        if digits == 0 {
+            return Err(invalid());
         }
+
+        rest = &rest[1 + digits..];
     }
 
-    return false;
+    let offset;
+    match rest {
+        "Z" | "z" => offset = 0,
+        _ if rest.len() == 6 && (rest.starts_with('+') || rest.starts_with('-')) => {
+            if rest.as_bytes()[3] != b':' {
+                return Err(invalid());
+            }
+
+            let hours = number(text.len() - 5..text.len() - 3)?;
+            let minutes = number(text.len() - 2..text.len())?;
+            let sign = if rest.starts_with('-') { -1 } else { 1 };
+            offset = sign * (hours * 3600 + minutes * 60);
+        }
+        _ => return Err(invalid()),
+    }
+
+    let days_in_month = [31, 28, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+    let leap = (year % 4 == 0 && year % 100 != 0) || year % 400 == 0;
+    if !(1..=12).contains(&month)
+        || day < 1
+        || day > days_in_month[month as usize - 1] + if month == 2 && leap { 1 } else { 0 }
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return Err(invalid());
+    }
+
+    // The number of days since 1970-01-01 in the proleptic Gregorian calendar.
+    let (y, m) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let era = y / 400;
+    let year_of_era = y - era * 400;
+    let day_of_year = (153 * m + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+
+    let time = days * 86400 + hour * 3600 + minute * 60 + second - offset;
+    if time < 0 {
+        return Err(String::from("The time must not be before 1970."));
+    }
+
+    return Ok(time as u64);
+}
+
+/// Splits a comma separated list of tags, dropping empty and repeated tags.
+pub fn parse_tags(tags: &str) -> Vec<String> {
+    let mut parsed: Vec<String> = Vec::new();
+
+    for tag in tags.split(',').map(|t| t.trim()) {
+        if !tag.is_empty()
+            && !parsed
+                .iter()
+                .any(|p| p.to_lowercase() == tag.to_lowercase())
+        {
+            parsed.push(String::from(tag));
+        }
+    }
+
+    return parsed;
+}
+
+/// Matches the characters of the query in order against the text, ignoring case, so that "ghw"
+/// matches "GitHub work". Returns a score, higher for consecutive characters and characters at the
+/// start of words (including camel case ones), and the positions of the matched characters.
+pub fn fuzzy_match(query: &str, text: &str) -> Option<(i64, Vec<usize>)> {
+    let query: Vec<char> = query
+        .to_lowercase()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    let text: Vec<char> = text.chars().collect();
+    let lowercase: Vec<char> = text
+        .iter()
+        .map(|c| c.to_lowercase().next().unwrap_or(*c))
+        .collect();
+
+    if query.is_empty() {
+        return Some((0, Vec::new()));
+    }
+
+    let mut best: Option<(i64, Vec<usize>)> = None;
+
+    // Matching greedily from each occurrence of the first character finds the better alignments,
+    // e.g. the "w" of "work" rather than the one in "GitHub wiki work".
+    for start in (0..lowercase.len()).filter(|&i| lowercase[i] == query[0]) {
+        let mut positions = vec![start];
+        let mut position = start + 1;
+
+        for c in query.iter().skip(1) {
+            match (position..lowercase.len()).find(|&i| lowercase[i] == *c) {
+                Some(i) => {
+                    positions.push(i);
+                    position = i + 1;
+                }
+                None => break,
+            }
+        }
+
+        if positions.len() != query.len() {
+            break;
+        }
+
+        let mut score = 0;
+
+        for (n, &i) in positions.iter().enumerate() {
+            score += 1;
+
+            let word_start = i == 0
+                || !text[i - 1].is_alphanumeric()
+                || (text[i].is_uppercase() && text[i - 1].is_lowercase());
+
+            if word_start {
+                score += 8;
+            }
+
+            if n > 0 {
+                if positions[n - 1] + 1 == i {
+                    score += 5;
+                } else {
+                    score -= (i - positions[n - 1]) as i64 / 4;
+                }
+            }
+        }
+
+        if best.as_ref().is_none_or(|(b, _)| score > *b) {
+            best = Some((score, positions));
+        }
+    }
+
+    return best;
+}
+
+/// Finds the item that a query given on the command line refers to. An exact label match is
+/// preferred, otherwise the query may be the issuer, the account or "issuer:account" of a single
+/// item, compared ignoring case. Failing that, the query may be a unique prefix of a label, and
+/// lastly it is fuzzy matched against the labels and descriptions. With `exact` only the label
+/// itself is accepted.
+pub fn find_item(query: &str, items: &Vec<Item>, exact: bool) -> Result<usize, String> {
+    if let Some(index) = items.iter().position(|item| item.label == query) {
+        return Ok(index);
+    }
+
+    if exact {
+        return Err(format!("No item with the label '{}' exists.", query));
+    }
+
+    let lowercase = query.trim().to_lowercase();
+    let matches: Vec<usize> = (0..items.len())
+        .filter(|&index| {
+            let issuer = items[index]
+                .issuer
+                .clone()
+                .unwrap_or_default()
+                .to_lowercase();
+            let account = items[index]
+                .account
+                .clone()
+                .unwrap_or_default()
+                .to_lowercase();
+
+            return (!issuer.is_empty() && issuer == lowercase)
+                || (!account.is_empty() && account == lowercase)
+                || (!(issuer.is_empty() && account.is_empty())
+                    && format!("{}:{}", issuer, account) == lowercase);
+        })
+        .collect();
+
+    if matches.is_empty() && !lowercase.is_empty() {
+        let prefixed: Vec<usize> = (0..items.len())
+            .filter(|&index| items[index].label.to_lowercase().starts_with(&lowercase))
+            .collect();
+
+        if !prefixed.is_empty() {
+            return single_match(query, items, prefixed);
+        }
+
+        // The best matches are listed first should the query be ambiguous.
+        let mut scored: Vec<(i64, usize)> = (0..items.len())
+            .filter_map(|index| {
+                let text = format!("{}  {}", items[index].label, items[index].description());
+                return fuzzy_match(query, &text).map(|(score, _)| (score, index));
+            })
+            .collect();
+        scored.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+
+        return single_match(query, items, scored.into_iter().map(|(_, i)| i).collect());
+    }
+
+    return single_match(query, items, matches);
+}
+
+/// Resolves the items matched by a query to a single one, listing the candidates otherwise.
+fn single_match(query: &str, items: &Vec<Item>, matches: Vec<usize>) -> Result<usize, String> {
+    match matches.len() {
+        0 => return Err(format!("No item matches '{}'.", query)),
+        1 => return Ok(matches[0]),
+        _ => {
+            let mut message = format!("'{}' matches several items:\n", query);
+
+            for index in matches {
+                let description = items[index].description();
+
+                if description.is_empty() {
+                    message.push_str(&format!("  {}\n", items[index].label));
+                } else {
+                    message.push_str(&format!("  {}  {}\n", items[index].label, description));
+                }
+            }
+
+            message.push_str("Use the label to choose one.");
+            return Err(message);
+        }
+    }
+}
+
+/// Obtains the database passphrase from the OTPC_PASSPHRASE environment variable, or by prompting
+/// for it without echo. A new passphrase must be entered twice to guard against typos.
+pub fn request_passphrase(new: bool) -> Result<String, String> {
+    return request_secret(
+        PASSPHRASE_VARIABLE,
+        if new {
+            "New database passphrase: "
+        } else {
+            "Database passphrase: "
+        },
+        new,
+    );
+}
+
+/// Obtains a password from an environment variable, or by prompting for it without echo. When
+/// confirm is set the password has to be entered twice.
+pub fn request_secret(variable: &str, prompt: &str, confirm: bool) -> Result<String, String> {
+    if let Ok(secret) = std::env::var(variable) {
+        if secret.is_empty() {
+            return Err(format!("{} is set but empty.", variable));
+        }
+
+        return Ok(secret);
+    }
+
+    loop {
+        let secret;
+        match rpassword::prompt_password(prompt) {
+            Ok(s) => secret = s,
+            Err(_) => return Err(String::from("Could not retrieve the password.")),
+        }
+
+        if secret.is_empty() {
+            eprintln!("The password cannot be empty.");
+            continue;
+        }
+
+        if !confirm {
+            return Ok(secret);
+        }
+
+        match rpassword::prompt_password("Confirm: ") {
+            Ok(confirmation) => {
+                if confirmation == secret {
+                    return Ok(secret);
+                }
+
+                eprintln!("The passwords do not match.");
+            }
+            Err(_) => return Err(String::from("Could not retrieve the password.")),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -72,6 +440,25 @@ mod test {
         assert!(!is_base_32_c('1'));
     }
 
+    #[test]
+    pub fn test_normalize_secret() {
+        use super::*;
+        assert_eq!(
+            normalize_secret("JBSW Y3DP EHPK 3PXP"),
+            Ok(String::from("jbswy3dpehpk3pxp"))
+        );
+        assert_eq!(
+            normalize_secret("jbsw-y3dp-ehpk-3pxp\n"),
+            Ok(String::from("jbswy3dpehpk3pxp"))
+        );
+        assert_eq!(normalize_secret("MFRGG==="), Ok(String::from("mfrgg")));
+        assert!(normalize_secret(" = ").is_err());
+        assert!(normalize_secret("JBSW1Y3D").is_err());
+        assert!(normalize_secret("MF=RGG").is_err());
+        assert!(normalize_secret("MFRGGZD").is_ok());
+        assert!(normalize_secret("MFRGGZ").is_err());
+    }
+
     #[test]
     pub fn test_is_number() {
         use super::*;
@@ -85,39 +472,111 @@ mod test {
     }
 
     #[test]
-    pub fn test_contains_white_space() {
+    pub fn test_import_label() {
+        use super::*;
+        assert_eq!(import_label("ACME Co", "john  doe"), "ACME Co:john doe");
+        assert_eq!(import_label("ACME", "ACME:john"), "ACME:john");
+        assert_eq!(import_label("", "john"), "john");
+    }
+
+    #[test]
+    pub fn test_parse_timestamp() {
         use super::*;
-        assert!(!contains_white_space(&String::from("123")));
+        assert_eq!(parse_timestamp("1111111109"), Ok(1111111109));
+        assert_eq!(parse_timestamp("2005-03-18T01:58:29Z"), Ok(1111111109));
+        assert_eq!(parse_timestamp("2005-03-18t01:58:29.75z"), Ok(1111111109));
+        assert_eq!(parse_timestamp("2005-03-18T03:58:29+02:00"), Ok(1111111109));
+        assert_eq!(parse_timestamp("2005-03-17T20:28:29-05:30"), Ok(1111111109));
+        assert_eq!(parse_timestamp("2000-02-29T00:00:00Z"), Ok(951782400));
+        assert_eq!(parse_timestamp("1970-01-01T00:00:00Z"), Ok(0));
+        assert!(parse_timestamp("2001-02-29T00:00:00Z").is_err());
+        assert!(parse_timestamp("2005-03-18T01:58:29").is_err());
+        assert!(parse_timestamp("2005-03-18").is_err());
+        assert!(parse_timestamp("1969-12-31T23:59:59Z").is_err());
+        assert!(parse_timestamp("yesterday").is_err());
     }
 
     #[test]
-    pub fn test_contains_white_space_fail_space() {
+    pub fn test_parse_tags() {
         use super::*;
-        assert!(contains_white_space(&String::from("1 23a")));
+        assert_eq!(
+            parse_tags(" work, personal,,Work "),
+            vec!["work", "personal"]
+        );
+        assert!(parse_tags("").is_empty());
     }
 
     #[test]
-    pub fn test_contains_white_space_fail_tab() {
+    pub fn test_fuzzy_match() {
         use super::*;
-        assert!(contains_white_space(&String::from("1\t23a")));
+        assert_eq!(fuzzy_match("ghw", "GitHub work").unwrap().1, vec![0, 3, 7]);
+        assert_eq!(
+            fuzzy_match("work", "GitHub wiki work").unwrap().1,
+            vec![12, 13, 14, 15]
+        );
+        assert!(
+            fuzzy_match("gh", "GitHub").unwrap().0 > fuzzy_match("gh", "a gitlab hub").unwrap().0
+        );
+        assert!(fuzzy_match("bank", "GitHub").is_none());
+        assert!(fuzzy_match("", "GitHub").is_some());
+    }
+
+    #[test]
+    pub fn test_find_item() {
+        use super::*;
+        let item = |label: &str, issuer: &str, account: &str| Item {
+            label: String::from(label),
+            secret: String::from("test"),
+            issuer: Some(String::from(issuer)),
+            account: Some(String::from(account)),
+            ..Default::default()
+        };
+        let items = vec![
+            item("github work", "GitHub", "jsmith"),
+            item("github personal", "GitHub", "john"),
+            item("bank", "ACME Bank", "jsmith"),
+        ];
+
+        assert_eq!(find_item("bank", &items, false), Ok(2));
+        assert_eq!(find_item("acme bank", &items, false), Ok(2));
+        assert_eq!(find_item("GitHub:john", &items, false), Ok(1));
+        assert!(find_item("GitHub", &items, false)
+            .unwrap_err()
+            .contains("several"));
+        assert!(find_item("jsmith", &items, false).is_err());
+        assert!(find_item("gitlab", &items, false).is_err());
+
+        // Unique prefixes and fuzzy matches.
+        assert_eq!(find_item("github p", &items, false), Ok(1));
+        assert_eq!(find_item("ba", &items, false), Ok(2));
+        assert_eq!(find_item("ghwk", &items, false), Ok(0));
+        assert!(find_item("git", &items, false)
+            .unwrap_err()
+            .contains("  github work  GitHub (jsmith)\n"));
+
+        // Only the label is accepted with exact matching.
+        assert_eq!(find_item("bank", &items, true), Ok(2));
+        assert!(find_item("ba", &items, true).is_err());
+        assert!(find_item("acme bank", &items, true).is_err());
+
+        // Items without an issuer or account don't have an "issuer:account" to match.
+        let unnamed = vec![item("first", "", "")];
+        assert!(find_item(":", &unnamed, false).is_err());
     }
 
     #[test]
     pub fn test_contains_item_label() {
         use super::*;
-        use crate::item::Digits;
         let items = vec![
             Item {
                 label: String::from("test1"),
                 secret: String::from("test"),
-                digits: Digits::Six,
-                split_time: 30,
+                ..Default::default()
             },
             Item {
                 label: String::from("test2"),
                 secret: String::from("test2"),
-                digits: Digits::Six,
-                split_time: 30,
+                ..Default::default()
             },
         ];
 
@@ -127,19 +586,16 @@ mod test {
     #[test]
     pub fn test_contains_item_label_fail() {
         use super::*;
-        use crate::item::Digits;
         let items = vec![
             Item {
                 label: String::from("test1"),
                 secret: String::from("test"),
-                digits: Digits::Six,
-                split_time: 30,
+                ..Default::default()
             },
             Item {
                 label: String::from("test2"),
                 secret: String::from("test2"),
-                digits: Digits::Six,
-                split_time: 30,
+                ..Default::default()
             },
         ];
 
@@ -0,0 +1,297 @@
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+
+const VAULT_FORMAT: &'static str = "otpc-vault";
+const VAULT_VERSION: u32 = 1;
+const KDF_ALGORITHM: &'static str = "argon2id";
+const CIPHER_ALGORITHM: &'static str = "xchacha20poly1305";
+const KEY_LENGTH: usize = 32;
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 24;
+// Limits on the argon2id parameters read from a vault header, so that a crafted header can't
+// make otpc allocate gigabytes or spin for minutes before the passphrase is checked.
+const MAX_MEMORY_KIB: u32 = 1024 * 1024;
+const MAX_ITERATIONS: u32 = 64;
+const MAX_PARALLELISM: u32 = 16;
+
+/// The argon2id cost parameters used to derive the vault key from the passphrase.
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
+pub struct KdfParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        return KdfParams {
+            memory_kib: 64 * 1024,
+            iterations: 3,
+            parallelism: 1,
+        };
+    }
+}
+
+/// Everything needed to re-derive the key and decrypt the payload. The serialized header is
+/// also passed to the cipher as associated data so that it cannot be altered undetected.
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
+struct Header {
+    format: String,
+    version: u32,
+    kdf: String,
+    kdf_params: KdfParams,
+    salt: String,
+    cipher: String,
+    nonce: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Vault {
+    header: Header,
+    data: String,
+}
+
+/// Returns true if the contents look like an encrypted vault rather than a plaintext database.
+pub fn is_vault(contents: &str) -> bool {
+    match serde_json::from_str::<Vault>(contents) {
+        Ok(vault) => return vault.header.format == VAULT_FORMAT,
+        Err(_) => return false,
+    }
+}
+
+pub fn seal(plaintext: &[u8], passphrase: &str) -> Result<String, String> {
+    return seal_with_params(plaintext, passphrase, seal_params());
+}
+
+/// The parameters new vaults are sealed with. Tests seal with cheap ones instead, so that they
+/// don't spend most of their time deriving keys.
+fn seal_params() -> KdfParams {
+    #[cfg(test)]
+    return test_params();
+    #[cfg(not(test))]
+    return KdfParams::default();
+}
+
+#[cfg(test)]
+pub(crate) fn test_params() -> KdfParams {
+    return KdfParams {
+        memory_kib: 64,
+        iterations: 1,
+        parallelism: 1,
+    };
+}
+
+pub fn seal_with_params(
+    plaintext: &[u8],
+    passphrase: &str,
+    params: KdfParams,
+) -> Result<String, String> {
+    let mut salt = [0u8; SALT_LENGTH];
+    let mut nonce = [0u8; NONCE_LENGTH];
+
+    if getrandom::getrandom(&mut salt).is_err() || getrandom::getrandom(&mut nonce).is_err() {
+        return Err(String::from(
+            "Could not generate random data for encryption.",
+        ));
+    }
+
+    let header = Header {
+        format: String::from(VAULT_FORMAT),
+        version: VAULT_VERSION,
+        kdf: String::from(KDF_ALGORITHM),
+        kdf_params: params,
+        salt: BASE64.encode(salt),
+        cipher: String::from(CIPHER_ALGORITHM),
+        nonce: BASE64.encode(nonce),
+    };
+
+    let key = derive_key(passphrase, &salt, &header.kdf_params)?;
+    let aad = associated_data(&header)?;
+
+    let ciphertext;
+    match XChaCha20Poly1305::new(&key.into()).encrypt(
+        XNonce::from_slice(&nonce),
+        Payload {
+            msg: plaintext,
+            aad: &aad,
+        },
+    ) {
+        Ok(c) => ciphertext = c,
+        Err(_) => return Err(String::from("Could not encrypt the database.")),
+    }
+
+    let vault = Vault {
+        header,
+        data: BASE64.encode(ciphertext),
+    };
+
+    match serde_json::to_string(&vault) {
+        Ok(s) => return Ok(s),
+        Err(e) => return Err(e.to_string()),
+    }
+}
+
+pub fn open(contents: &str, passphrase: &str) -> Result<Vec<u8>, String> {
+    let vault: Vault;
+    match serde_json::from_str(contents) {
+        Ok(v) => vault = v,
+        Err(e) => return Err(format!("The vault is malformed: {}", e)),
+    }
+
+    let header = &vault.header;
+
+    if header.format != VAULT_FORMAT {
+        return Err(String::from("The file is not an otpc vault."));
+    }
+
+    if header.version > VAULT_VERSION {
+        return Err(format!(
+            "The vault was written by a newer version of otpc (vault version {}).",
+            header.version
+        ));
+    }
+
+    if header.kdf != KDF_ALGORITHM || header.cipher != CIPHER_ALGORITHM {
+        return Err(String::from(
+            "The vault uses an unsupported key derivation function or cipher.",
+        ));
+    }
+
+    let salt = decode_field(&header.salt, "salt")?;
+    let nonce = decode_field(&header.nonce, "nonce")?;
+    let ciphertext = decode_field(&vault.data, "data")?;
+
+    if nonce.len() != NONCE_LENGTH {
+        return Err(String::from("The vault nonce has an invalid length."));
+    }
+
+    let key = derive_key(passphrase, &salt, &header.kdf_params)?;
+    let aad = associated_data(header)?;
+
+    match XChaCha20Poly1305::new(&key.into()).decrypt(
+        XNonce::from_slice(&nonce),
+        Payload {
+            msg: &ciphertext,
+            aad: &aad,
+        },
+    ) {
+        Ok(plaintext) => return Ok(plaintext),
+        Err(_) => {
+            return Err(String::from(
+                "Could not decrypt the database, the passphrase may be incorrect.",
+            ))
+        }
+    }
+}
+
+fn derive_key(
+    passphrase: &str,
+    salt: &[u8],
+    params: &KdfParams,
+) -> Result<[u8; KEY_LENGTH], String> {
+    if params.memory_kib > MAX_MEMORY_KIB
+        || params.iterations > MAX_ITERATIONS
+        || params.parallelism > MAX_PARALLELISM
+    {
+        return Err(String::from(
+            "The key derivation parameters are more expensive than otpc allows.",
+        ));
+    }
+
+    let argon_params;
+    match Params::new(
+        params.memory_kib,
+        params.iterations,
+        params.parallelism,
+        Some(KEY_LENGTH),
+    ) {
+        Ok(p) => argon_params = p,
+        Err(e) => return Err(format!("Invalid key derivation parameters: {}", e)),
+    }
+
+    let mut key = [0u8; KEY_LENGTH];
+
+    match Argon2::new(Algorithm::Argon2id, Version::V0x13, argon_params).hash_password_into(
+        passphrase.as_bytes(),
+        salt,
+        &mut key,
+    ) {
+        Ok(_) => return Ok(key),
+        Err(e) => return Err(format!("Could not derive the vault key: {}", e)),
+    }
+}
+
+fn associated_data(header: &Header) -> Result<Vec<u8>, String> {
+    match serde_json::to_vec(header) {
+        Ok(v) => return Ok(v),
+        Err(e) => return Err(e.to_string()),
+    }
+}
+
+fn decode_field(value: &str, name: &str) -> Result<Vec<u8>, String> {
+    match BASE64.decode(value) {
+        Ok(v) => return Ok(v),
+        Err(_) => return Err(format!("The vault {} is not valid base-64.", name)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    pub fn test_seal_open() {
+        use super::*;
+        let sealed = seal_with_params(b"[]", "hunter2", test_params()).unwrap();
+
+        assert!(is_vault(&sealed));
+        assert_eq!(open(&sealed, "hunter2").unwrap(), b"[]");
+    }
+
+    #[test]
+    pub fn test_open_wrong_passphrase() {
+        use super::*;
+        let sealed = seal_with_params(b"[]", "hunter2", test_params()).unwrap();
+
+        assert!(open(&sealed, "hunter3").is_err());
+    }
+
+    #[test]
+    pub fn test_open_tampered_header() {
+        use super::*;
+        let sealed = seal_with_params(b"[]", "hunter2", test_params()).unwrap();
+        let tampered = sealed.replace("\"iterations\":1", "\"iterations\":2");
+
+        assert!(open(&tampered, "hunter2").is_err());
+    }
+
+    #[test]
+    pub fn test_open_newer_version() {
+        use super::*;
+        let sealed = seal_with_params(b"[]", "hunter2", test_params()).unwrap();
+        let newer = sealed.replace("\"version\":1", "\"version\":99");
+
+        assert!(open(&newer, "hunter2")
+            .unwrap_err()
+            .contains("newer version"));
+    }
+
+    #[test]
+    pub fn test_open_expensive_params() {
+        use super::*;
+        let sealed = seal_with_params(b"[]", "hunter2", test_params()).unwrap();
+        let expensive = sealed.replace("\"memory_kib\":64", "\"memory_kib\":4194304");
+
+        assert!(open(&expensive, "hunter2")
+            .unwrap_err()
+            .contains("more expensive"));
+    }
+
+    #[test]
+    pub fn test_is_vault_plaintext() {
+        use super::*;
+        assert!(!is_vault("[]"));
+    }
+}